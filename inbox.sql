@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS inbox (
+                             id BIGSERIAL PRIMARY KEY,
+                             message_id VARCHAR(64) UNIQUE NOT NULL,
+                             message_type VARCHAR(1024) NOT NULL,
+                             received TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                             "timestamp" TIMESTAMPTZ NOT NULL,
+                             body TEXT NOT NULL,
+                             trace_parent VARCHAR(55) DEFAULT NULL
+);
+
+COMMENT ON COLUMN inbox.message_id IS 'The id of the message, a message is only ever inserted once';
+COMMENT ON COLUMN inbox.message_type IS 'The Type of message';
+COMMENT ON COLUMN inbox.received IS 'The time that the message was delivered to the inbox';
+COMMENT ON COLUMN inbox."timestamp" IS 'The time that this message was placed in the outbox';
+COMMENT ON COLUMN inbox.body IS 'The payload of the message';
+COMMENT ON COLUMN inbox.trace_parent IS 'The Open Telemetry Parent Trace Id';
@@ -9,7 +9,11 @@ CREATE TABLE core.outbox (
                              dispatched TIMESTAMPTZ DEFAULT NULL,
                              "timestamp" TIMESTAMPTZ NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
                              body TEXT NOT NULL,
-                             trace_parent VARCHAR(55) DEFAULT NULL
+                             trace_parent VARCHAR(55) DEFAULT NULL,
+                             partition_key VARCHAR(256) DEFAULT NULL,
+                             headers JSONB DEFAULT NULL,
+                             dead_lettered TIMESTAMPTZ DEFAULT NULL,
+                             dead_letter_reason TEXT DEFAULT NULL
 );
 
 COMMENT ON COLUMN core.outbox.message_id IS 'The id of the message';
@@ -19,5 +23,24 @@ COMMENT ON COLUMN core.outbox.dispatched IS 'The time that the message was dispa
 COMMENT ON COLUMN core.outbox."timestamp" IS 'The time that this message was placed in the outbox';
 COMMENT ON COLUMN core.outbox.body IS 'The payload of the message';
 COMMENT ON COLUMN core.outbox.trace_parent IS 'The Open Telemetry Parent Trace Id';
+COMMENT ON COLUMN core.outbox.partition_key IS 'The key used to partition the message, defaults to the message_id';
+COMMENT ON COLUMN core.outbox.headers IS 'A JSON object of headers, sent as SQS and SNS message attributes';
+COMMENT ON COLUMN core.outbox.dead_lettered IS 'The time that the message was given up on, it will not be dispatched again';
+COMMENT ON COLUMN core.outbox.dead_letter_reason IS 'Why the message could not be dispatched';
 
-CREATE INDEX idx_outbox_dispatched ON core.outbox (dispatched);
\ No newline at end of file
+CREATE INDEX idx_outbox_dispatched ON core.outbox (dispatched);
+
+CREATE TABLE core.outbox_deliveries (
+                             outbox_id BIGINT NOT NULL REFERENCES core.outbox (id),
+                             channel_address VARCHAR(2048) NOT NULL,
+                             dispatched TIMESTAMPTZ DEFAULT NULL,
+                             dead_lettered TIMESTAMPTZ DEFAULT NULL,
+                             dead_letter_reason TEXT DEFAULT NULL,
+                             PRIMARY KEY (outbox_id, channel_address)
+);
+
+COMMENT ON TABLE core.outbox_deliveries IS 'The destinations a routed message has been delivered to';
+COMMENT ON COLUMN core.outbox_deliveries.channel_address IS 'The destination the message was routed to';
+COMMENT ON COLUMN core.outbox_deliveries.dispatched IS 'The time that the message was dispatched to this destination';
+COMMENT ON COLUMN core.outbox_deliveries.dead_lettered IS 'The time that this destination was given up on';
+COMMENT ON COLUMN core.outbox_deliveries.dead_letter_reason IS 'Why the message could not be dispatched to this destination';
@@ -1,19 +1,191 @@
-use aws_config::{BehaviorVersion, Region};
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_config::sts::AssumeRoleProvider;
+use aws_credential_types::provider::SharedCredentialsProvider;
 use crate::config::Config;
 use aws_sdk_sqs::Client as SqsClient;
 use aws_sdk_sns::Client as SnsClient;
+use aws_sdk_eventbridge::Client as EventBridgeClient;
+use aws_sdk_kinesis::Client as KinesisClient;
+use aws_sdk_kms::Client as KmsClient;
+use aws_sdk_s3::Client as S3Client;
+use rdkafka::ClientConfig;
+use rdkafka::error::KafkaError;
+use rdkafka::producer::FutureProducer;
 use sqlx::{postgres::PgPoolOptions, PgPool};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
 
-/// Creates and returns a new database connection pool.
-pub async fn setup_db_pool(config: &Config) -> Result<PgPool, sqlx::Error> {
+/// The session name the sweeper assumes roles with, so its calls can be told apart in CloudTrail.
+const ROLE_SESSION_NAME: &str = "outbox-sweeper";
+
+/// The options used for every Postgres connection pool.
+pub fn db_pool_options() -> PgPoolOptions {
     PgPoolOptions::new()
         .max_connections(5)
-        .connect(&config.database_url())
+}
+
+/// Creates and returns a new database connection pool.
+pub async fn setup_db_pool(config: &Config) -> Result<PgPool, sqlx::Error> {
+    db_pool_options()
+        .connect(config.database_url())
         .await
 }
 
-/// Creates and returns a new AWS SQS client.
-pub async fn setup_aws_clients(config: &Config) -> (SqsClient, SnsClient) {
+/// The clients for every AWS service the sweeper can dispatch to.
+#[derive(Clone)]
+pub struct AwsClients {
+    pub sqs: SqsClient,
+    pub sns: SnsClient,
+    pub eventbridge: EventBridgeClient,
+    pub kinesis: KinesisClient,
+    pub kms: KmsClient,
+    pub s3: S3Client,
+    config: SdkConfig,
+}
+
+impl AwsClients {
+    /// Creates clients for every service that share the same config.
+    pub fn from_config(config: &SdkConfig) -> Self {
+        AwsClients {
+            sqs: SqsClient::new(config),
+            sns: SnsClient::new(config),
+            eventbridge: EventBridgeClient::new(config),
+            kinesis: KinesisClient::new(config),
+            kms: KmsClient::new(config),
+            s3: S3Client::new(config),
+            config: config.clone(),
+        }
+    }
+
+    /// The region the clients make their calls in.
+    pub fn region(&self) -> Option<&str> {
+        self.config.region().map(Region::as_ref)
+    }
+
+    /// Creates clients for another region, sending their calls to the endpoint URL if one is given.
+    pub fn in_region(&self, region: &str, endpoint_url: Option<&str>) -> Self {
+        let mut config = self.config.to_builder().region(Region::new(region.to_string()));
+        if let Some(endpoint_url) = endpoint_url {
+            config = config.endpoint_url(endpoint_url);
+        }
+        Self::from_config(&config.build())
+    }
+
+    /// Creates clients that act as the role, using these clients' credentials to assume it.
+    ///
+    /// The role is assumed through STS on first use, and its credentials are cached and refreshed
+    /// before they expire by the SDK's identity cache.
+    pub async fn assume_role(&self, role_arn: &str, external_id: Option<&str>) -> Self {
+        let mut provider = AssumeRoleProvider::builder(role_arn).session_name(ROLE_SESSION_NAME).configure(&self.config);
+        if let Some(external_id) = external_id {
+            provider = provider.external_id(external_id);
+        }
+        let credentials = SharedCredentialsProvider::new(provider.build().await);
+        Self::from_config(&self.config.to_builder().credentials_provider(credentials).build())
+    }
+}
+
+/// The clients for each region channels are in, created the first time a region is sent to.
+#[derive(Default)]
+pub struct RegionalClients {
+    clients: std::sync::Mutex<HashMap<String, AwsClients>>,
+}
+
+impl RegionalClients {
+    /// Returns the clients for the region, creating them from the base clients on first use.
+    pub fn get(&self, base: &AwsClients, region: &str, endpoint_url: Option<&str>) -> AwsClients {
+        let mut clients = self.clients.lock().expect("regional clients lock was poisoned");
+        clients.entry(region.to_string()).or_insert_with(|| base.in_region(region, endpoint_url)).clone()
+    }
+}
+
+/// The region, role ARN and external id a set of assumed role clients was created for.
+type AssumedRole = (Option<String>, String, Option<String>);
+
+/// The clients for each role channels assume, keyed by region, role ARN and external id.
+#[derive(Default)]
+pub struct AssumedRoleClients {
+    clients: Mutex<HashMap<AssumedRole, AwsClients>>,
+}
+
+impl AssumedRoleClients {
+    /// Returns the clients for the role, creating them from the base clients on first use.
+    pub async fn get(&self, base: &AwsClients, role_arn: &str, external_id: Option<&str>) -> AwsClients {
+        let mut clients = self.clients.lock().await;
+        let key = (base.region().map(str::to_string), role_arn.to_string(), external_id.map(str::to_string));
+        if let Some(role_clients) = clients.get(&key) {
+            return role_clients.clone();
+        }
+        let role_clients = base.assume_role(role_arn, external_id).await;
+        clients.insert(key, role_clients.clone());
+        role_clients
+    }
+}
+
+/// Creates and returns new AWS clients that share the same config.
+pub async fn setup_aws_clients(config: &Config) -> AwsClients {
+    // Pinned so upgrading the SDK does not change retry and timeout defaults underneath us
+    #[allow(deprecated)]
     let aws_config = aws_config::defaults(BehaviorVersion::v2025_08_07()).region(Region::new(config.aws_region.clone())).load().await;
-    (SqsClient::new(&aws_config), SnsClient::new(&aws_config))
+    AwsClients::from_config(&aws_config)
+}
+
+/// Creates a Kafka producer if `KAFKA_BOOTSTRAP_SERVERS` is set.
+pub fn setup_kafka_producer(config: &Config) -> Result<Option<FutureProducer>, KafkaError> {
+    config.kafka_bootstrap_servers
+        .as_deref()
+        .map(|servers| kafka_producer_config(servers, config.kafka_message_timeout_ms).create())
+        .transpose()
+}
+
+/// The producer settings used for Kafka.
+///
+/// Idempotence is enabled so that librdkafka's internal retries can not duplicate or reorder
+/// messages within a partition.
+pub fn kafka_producer_config(bootstrap_servers: &str, message_timeout_ms: u64) -> ClientConfig {
+    let mut client_config = ClientConfig::new();
+    client_config
+        .set("bootstrap.servers", bootstrap_servers)
+        .set("enable.idempotence", "true")
+        .set("acks", "all")
+        .set("max.in.flight.requests.per.connection", "5")
+        .set("message.timeout.ms", message_timeout_ms.to_string());
+    client_config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_role_clients_are_cached_per_role() {
+        let base_config = aws_config::defaults(BehaviorVersion::latest()).region(Region::new("eu-west-1")).no_credentials().load().await;
+        let base = AwsClients::from_config(&base_config);
+        let assumed_roles = AssumedRoleClients::default();
+        let role_arn = "arn:aws:iam::111111111111:role/outbox-publisher";
+
+        let role_clients = assumed_roles.get(&base, role_arn, Some("tenant-a")).await;
+        assumed_roles.get(&base, role_arn, Some("tenant-a")).await;
+        assumed_roles.get(&base, role_arn, Some("tenant-b")).await;
+
+        assert!(role_clients.config.credentials_provider().is_some(), "The role clients should have the assumed role's credentials");
+        assert_eq!(assumed_roles.clients.lock().await.len(), 2, "Each role and external id should get its own clients, once");
+    }
+
+    #[tokio::test]
+    async fn test_regional_clients_are_cached_per_region() {
+        let base_config = aws_config::defaults(BehaviorVersion::latest()).region(Region::new("eu-west-1")).no_credentials().load().await;
+        let base = AwsClients::from_config(&base_config);
+        let regional_clients = RegionalClients::default();
+
+        let us_east = regional_clients.get(&base, "us-east-1", Some("http://localhost:4566"));
+        regional_clients.get(&base, "us-east-1", Some("http://localhost:4566"));
+        let ap_southeast = regional_clients.get(&base, "ap-southeast-2", None);
+
+        assert_eq!(us_east.region(), Some("us-east-1"));
+        assert_eq!(us_east.config.endpoint_url(), Some("http://localhost:4566"));
+        assert_eq!(ap_southeast.region(), Some("ap-southeast-2"));
+        assert_eq!(ap_southeast.config.endpoint_url(), None);
+        assert_eq!(regional_clients.clients.lock().unwrap().len(), 2);
+    }
 }
@@ -1,4 +1,14 @@
 use serde::Deserialize;
+use crate::messaging::cloudevents::{self, CloudEventsMode};
+use crate::messaging::compression::Compression;
+use crate::messaging::attributes::MAX_MESSAGE_ATTRIBUTES;
+use crate::messaging::encryption::{self, EncryptionSettings};
+use crate::messaging::Channel;
+use crate::models::{OutboxColumn, OutboxMessage};
+use crate::routing::{needs_routing, RoutingRule};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::LazyLock;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
@@ -8,12 +18,21 @@ pub struct Config {
     pub aws_region: String,
     pub batch_size: i32,
     pub sentry_dsn: Option<String>,
+    pub kafka_bootstrap_servers: Option<String>,
+    #[serde(default = "default_kafka_message_timeout")]
+    pub kafka_message_timeout_ms: u64,
+    pub channel_config_path: Option<String>,
+    pub schema_dir: Option<String>,
 }
 
 fn default_sweep_interval() -> u64 {
     5000 // Default to 5 seconds
 }
 
+fn default_kafka_message_timeout() -> u64 {
+    30000 // Default to 30 seconds
+}
+
 impl Config {
     pub fn load() -> Result<Self, envy::Error> {
         dotenvy::dotenv().ok();
@@ -46,3 +65,313 @@ impl Config {
             .expect("DATABASE_URL is not set")
     }
 }
+
+/// Settings that only apply to a single channel address.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelSettings {
+    /// The secret used to sign webhook requests with HMAC-SHA256.
+    pub hmac_secret: Option<String>,
+    /// How long a webhook request may take before it is abandoned.
+    pub timeout_ms: Option<u64>,
+    /// The exchange AMQP messages are published to, `amq.topic` by default.
+    pub exchange: Option<String>,
+    /// The `Source` of events put on an EventBridge bus, and of CloudEvents.
+    pub source: Option<String>,
+    /// Trims a Redis Stream to roughly this many entries with `MAXLEN ~`.
+    pub max_len: Option<usize>,
+    /// The inbox table Postgres messages are inserted into, `inbox` by default.
+    pub table: Option<String>,
+    /// Outbox columns added to every SNS message as `String` attributes, for subscription filter policies.
+    pub filter_attributes: Option<Vec<OutboxColumn>>,
+    /// The S3 bucket that SQS and SNS bodies over 256 KiB are offloaded to.
+    pub payload_bucket: Option<String>,
+    /// Compresses SQS and SNS bodies, which are then base64 encoded.
+    pub compression: Option<Compression>,
+    /// Only bodies larger than this are compressed, 1024 bytes by default.
+    pub compression_threshold_bytes: Option<usize>,
+    /// Encrypts SQS and SNS bodies with AES-256-GCM under a data key from this provider.
+    pub encryption: Option<EncryptionSettings>,
+    /// Sends messages as CloudEvents, in structured or binary mode.
+    pub cloud_events: Option<CloudEventsMode>,
+    /// The most transport calls the sweeper makes to the channel a second.
+    #[serde(deserialize_with = "positive_rate")]
+    pub requests_per_second: Option<f64>,
+    /// The most messages the sweeper sends to the channel a second.
+    #[serde(deserialize_with = "positive_rate")]
+    pub messages_per_second: Option<f64>,
+    /// The IAM role SQS, SNS, EventBridge and Kinesis calls for the channel are made as, for
+    /// destinations in other AWS accounts.
+    pub role_arn: Option<String>,
+    /// The external id the role's trust policy requires, if any.
+    pub external_id: Option<String>,
+    /// How many calls in a row must fail before the channel's circuit opens, 5 by default.
+    pub circuit_failure_threshold: Option<u32>,
+    /// How long an open circuit skips the channel before a trial call, 30 seconds by default.
+    pub circuit_cooldown_ms: Option<u64>,
+    /// A secondary destination, such as a queue in another region, that messages are sent to
+    /// while the channel's circuit is open or once its retry budget has run out.
+    pub failover_address: Option<String>,
+    /// How many calls to the channel are made in a row in one sweep before the failover
+    /// destination is used, 1 by default.
+    pub retry_budget: Option<u32>,
+    /// A destination that a copy of a sample of the channel's messages is sent to, such as a new
+    /// queue or a JSONL file, without affecting the channel itself.
+    pub shadow_address: Option<String>,
+    /// The percentage of messages copied to the shadow destination, 100 by default.
+    #[serde(deserialize_with = "percentage")]
+    pub shadow_percent: Option<f64>,
+}
+
+fn percentage<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    let percent = f64::deserialize(deserializer)?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(serde::de::Error::custom(format!("percentages must be between 0 and 100, found {percent}")));
+    }
+    Ok(Some(percent))
+}
+
+fn positive_rate<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    let rate = f64::deserialize(deserializer)?;
+    if !(rate > 0.0 && rate.is_finite()) {
+        return Err(serde::de::Error::custom(format!("rates must be greater than zero, found {rate}")));
+    }
+    Ok(Some(rate))
+}
+
+impl ChannelSettings {
+    /// The source events are published with, `outbox-sweeper` by default.
+    pub fn source(&self) -> &str {
+        self.source.as_deref().unwrap_or("outbox-sweeper")
+    }
+
+    /// Describes why the settings can not be used for the channel, such as a body encoding its
+    /// transport does not apply.
+    fn check(&self, channel: Channel) -> Result<(), String> {
+        let aws_messaging = matches!(channel, Channel::Sqs(_) | Channel::Sns(_));
+        if self.cloud_events == Some(CloudEventsMode::Binary) && !channel.supports_headers() {
+            return Err(format!("CloudEvents binary mode needs headers, which {} channels can not carry", channel.kind()));
+        }
+        if self.compression.is_some() && !aws_messaging {
+            return Err(format!("compression is only supported on SQS and SNS channels, not {}", channel.kind()));
+        }
+        if self.encryption.is_some() && !aws_messaging {
+            return Err(format!("encryption is only supported on SQS and SNS channels, not {}", channel.kind()));
+        }
+        if let Some(encryption) = &self.encryption {
+            encryption.check()?;
+        }
+        let attributes = self.added_attributes(channel);
+        if aws_messaging && attributes > MAX_MESSAGE_ATTRIBUTES {
+            return Err(format!("the settings add up to {attributes} message attributes, at most {MAX_MESSAGE_ATTRIBUTES} are allowed"));
+        }
+        Ok(())
+    }
+
+    /// The most message attributes the settings add to an SQS or SNS message, before its headers.
+    fn added_attributes(&self, channel: Channel) -> usize {
+        let cloud_events = if self.cloud_events == Some(CloudEventsMode::Binary) { cloudevents::MAX_BINARY_ATTRIBUTES } else { 0 };
+        let filter_attributes = match channel {
+            Channel::Sns(_) => self.filter_attributes.as_ref().map_or(0, Vec::len),
+            _ => 0,
+        };
+        let encryption = if self.encryption.is_some() { encryption::ATTRIBUTES.len() } else { 0 };
+        cloud_events + filter_attributes + usize::from(self.compression.is_some()) + encryption + usize::from(self.payload_bucket.is_some())
+    }
+}
+
+/// Settings for the AWS clients of a single region.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegionSettings {
+    /// Sends the region's AWS calls here instead of to the AWS endpoint, e.g. for LocalStack.
+    pub endpoint_url: Option<String>,
+}
+
+static DEFAULT_CHANNEL_SETTINGS: LazyLock<ChannelSettings> = LazyLock::new(ChannelSettings::default);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChannelConfigError {
+    #[error("failed to read channel config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse channel config: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("invalid settings for channel {0}: {1}")]
+    Invalid(String, String),
+}
+
+/// Per channel settings and routes, loaded from the TOML file at `CHANNEL_CONFIG_PATH`.
+///
+/// Channels are keyed by their full channel address, routes by message type, and rules are tried
+/// in order, e.g.
+///
+/// ```toml
+/// [channels."https://example.com/hooks/orders"]
+/// hmac_secret = "change-me"
+/// timeout_ms = 2000
+///
+/// [routes]
+/// "order.created" = ["https://example.com/hooks/orders", "kafka://orders"]
+///
+/// [[rules]]
+/// message_type = "order.updated"
+/// when = "$.region == 'eu'"
+/// destination = "kafka://eu-orders"
+///
+/// [transforms]
+/// "order.created" = "scripts/order_created.rhai"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelConfig {
+    #[serde(default)]
+    pub channels: HashMap<String, ChannelSettings>,
+    /// The destinations each message type is fanned out to, in place of its `channel_address`.
+    #[serde(default)]
+    pub routes: HashMap<String, Vec<String>>,
+    /// Picks the destination of messages without a `channel_address`, the first match wins.
+    #[serde(default)]
+    pub rules: Vec<RoutingRule>,
+    /// Settings for the AWS clients of destinations in each region, keyed by region name.
+    #[serde(default)]
+    pub regions: HashMap<String, RegionSettings>,
+    /// The Rhai script each message type is transformed with before it is sent.
+    #[serde(default)]
+    pub transforms: HashMap<String, PathBuf>,
+}
+
+impl ChannelConfig {
+    pub fn load(path: &str) -> Result<Self, ChannelConfigError> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::parse(&contents)
+    }
+
+    /// Parses the config and checks every channel's settings can be applied to it, so a setting
+    /// that its transport would ignore stops the sweeper from starting.
+    pub fn parse(contents: &str) -> Result<Self, ChannelConfigError> {
+        let config: Self = toml::from_str(contents)?;
+        for (channel_address, settings) in &config.channels {
+            settings.check(Channel::parse(channel_address))
+                .map_err(|reason| ChannelConfigError::Invalid(channel_address.clone(), reason))?;
+        }
+        Ok(config)
+    }
+
+    /// Returns the destinations a message type is routed to, if it has any.
+    pub fn routes(&self, message_type: &str) -> Option<&[String]> {
+        self.routes.get(message_type).map(Vec::as_slice).filter(|routes| !routes.is_empty())
+    }
+
+    /// Returns where a message should be sent, which is its `channel_address` unless that is empty
+    /// or the routing placeholder, in which case it is the destination of the first matching rule.
+    pub fn destination<'m>(&'m self, msg: &'m OutboxMessage) -> Option<&'m str> {
+        if !needs_routing(msg) {
+            return Some(&msg.channel_address);
+        }
+        self.rules.iter().find(|rule| rule.matches(msg)).map(|rule| rule.destination.as_str())
+    }
+
+    /// Returns the endpoint URL AWS calls to the region should be sent to, if it has one configured.
+    pub fn endpoint_url(&self, region: &str) -> Option<&str> {
+        self.regions.get(region)?.endpoint_url.as_deref()
+    }
+
+    /// Returns the settings for a channel, or the defaults if it has none configured.
+    pub fn settings(&self, channel_address: &str) -> &ChannelSettings {
+        self.channels.get(channel_address).unwrap_or(&DEFAULT_CHANNEL_SETTINGS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_reason(contents: &str) -> String {
+        match ChannelConfig::parse(contents) {
+            Err(ChannelConfigError::Invalid(_, reason)) => reason,
+            other => panic!("Expected the settings to be rejected, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_binary_cloud_events_are_rejected_on_channels_without_headers() {
+        let reason = invalid_reason(r#"
+            [channels."redis://localhost:6379/events"]
+            cloud_events = "binary"
+        "#);
+
+        assert_eq!(reason, "CloudEvents binary mode needs headers, which Redis channels can not carry");
+        assert!(ChannelConfig::parse(r#"
+            [channels."redis://localhost:6379/events"]
+            cloud_events = "structured"
+        "#).is_ok(), "Structured mode does not need headers");
+    }
+
+    #[test]
+    fn test_compression_is_rejected_on_channels_that_would_ignore_it() {
+        let reason = invalid_reason(r#"
+            [channels."https://example.com/hooks/orders"]
+            compression = "gzip"
+        "#);
+
+        assert_eq!(reason, "compression is only supported on SQS and SNS channels, not Webhook");
+        assert!(ChannelConfig::parse(r#"
+            [channels."https://sqs.eu-west-1.amazonaws.com/000000000000/orders"]
+            compression = "zstd"
+        "#).is_ok());
+    }
+
+    #[test]
+    fn test_settings_must_leave_room_for_their_attributes() {
+        let reason = invalid_reason(r#"
+            [channels."SNS::arn:aws:sns:eu-west-1:111111111111:partner-events"]
+            cloud_events = "binary"
+            encryption = { provider = "kms", key_id = "alias/outbox-partner-events" }
+            compression = "gzip"
+            payload_bucket = "partner-events-large-payloads"
+        "#);
+
+        assert_eq!(reason, "the settings add up to 11 message attributes, at most 10 are allowed");
+        assert!(ChannelConfig::parse(r#"
+            [channels."SNS::arn:aws:sns:eu-west-1:111111111111:partner-events"]
+            cloud_events = "binary"
+            encryption = { provider = "kms", key_id = "alias/outbox-partner-events" }
+            compression = "gzip"
+        "#).is_ok(), "Exactly 10 attributes should be allowed");
+        assert!(ChannelConfig::parse(r#"
+            [channels."kafka://partner-events"]
+            cloud_events = "binary"
+        "#).is_ok(), "Only SQS and SNS limit their attributes");
+        assert!(matches!(ChannelConfig::parse(r#"
+            [channels."SNS::arn:aws:sns:eu-west-1:000000000000:domain-events"]
+            cloud_events = "binary"
+            filter_attributes = ["message_id", "message_type", "channel_address", "trace_parent", "partition_key"]
+        "#), Err(ChannelConfigError::Invalid(..))), "Filter attributes should count towards the limit");
+    }
+
+    #[test]
+    fn test_static_encryption_keys_must_be_32_bytes() {
+        for key in ["not base64!", "AAAAAAAAAAAAAAAAAAAAAA=="] {
+            let reason = invalid_reason(&format!(r#"
+                [channels."https://localhost.localstack.cloud:4566/000000000000/test-queue"]
+                encryption = {{ provider = "static", key_id = "local", key = "{key}" }}
+            "#));
+
+            assert_eq!(reason, "the static encryption key must be 32 base64 encoded bytes", "{key}");
+        }
+    }
+
+    #[test]
+    fn test_encryption_is_rejected_on_channels_that_would_ignore_it() {
+        let reason = invalid_reason(r#"
+            [channels."kafka://orders"]
+            encryption = { provider = "static", key_id = "local", key = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=" }
+        "#);
+
+        assert_eq!(reason, "encryption is only supported on SQS and SNS channels, not Kafka");
+        assert!(ChannelConfig::parse(r#"
+            [channels."SNS::arn:aws:sns:eu-west-1:111111111111:partner-events"]
+            encryption = { provider = "kms", key_id = "alias/outbox-partner-events" }
+        "#).is_ok());
+    }
+}
@@ -1,16 +1,25 @@
+#![recursion_limit = "256"]
+
 mod clients;
 mod config;
 mod models;
 mod sweeper;
 mod outbox;
 mod messaging;
-
-use crate::clients::{setup_db_pool, setup_aws_clients};
-use crate::config::Config;
+mod routing;
+
+use crate::clients::{setup_db_pool, setup_aws_clients, setup_kafka_producer};
+use crate::config::{ChannelConfig, Config};
+use crate::messaging::Dispatcher;
+use crate::messaging::circuit_breaker::CircuitBreakers;
+use crate::messaging::schema::Schemas;
+use crate::messaging::transform::Transforms;
 use crate::sweeper::sweep_outbox_and_send;
 
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
-use actix_web::{App, HttpResponse, HttpServer, Responder, get};
+use actix_web::{App, HttpResponse, HttpServer, Responder, get, web};
 use tokio::time;
 use tracing::{error, info, Level};
 use tracing_subscriber::layer::SubscriberExt;
@@ -23,6 +32,12 @@ async fn health_check() -> impl Responder {
     HttpResponse::Ok().body("OK")
 }
 
+#[get("/status")]
+async fn status(circuit_breakers: web::Data<CircuitBreakers>) -> impl Responder {
+    // The state of the circuit of every channel the sweeper has sent to
+    HttpResponse::Ok().json(serde_json::json!({ "circuits": circuit_breakers.status() }))
+}
+
 // Graceful shutdown signal future
     async fn shutdown_signal() {
         use tokio::signal;
@@ -40,9 +55,10 @@ async fn health_check() -> impl Responder {
         info!("Shutdown signal received. Exiting sweeper loop.");
     }
 
-async fn run_sweeper_logic(config : Config) {
-    // Setup logging
+async fn run_sweeper_logic(config : Config, circuit_breakers: Arc<CircuitBreakers>) {
+    // Setup logging, on stderr so a stdout:// channel writes nothing but its messages
     tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
         .with_env_filter(EnvFilter::from_default_env().add_directive(Level::INFO.into()))
         .init();
 
@@ -53,8 +69,30 @@ async fn run_sweeper_logic(config : Config) {
 
     // 2. Setup the AWS SQS Client
     info!("Setting up AWS clients...");
-    let (sqs_client, sns_client) = setup_aws_clients(&config).await;
-    info!("AWS SQS client established.");
+    let aws_clients = setup_aws_clients(&config).await;
+    info!("AWS clients established.");
+    let mut dispatcher = Dispatcher::new(aws_clients).with_circuit_breakers(circuit_breakers);
+
+    if let Some(path) = &config.channel_config_path {
+        let channel_config = ChannelConfig::load(path).expect("failed to load channel config.");
+        info!(channels = channel_config.channels.len(), rules = channel_config.rules.len(), "Channel config loaded.");
+        if !channel_config.transforms.is_empty() {
+            let transforms = Transforms::load(&channel_config.transforms).expect("failed to load transform scripts.");
+            info!(transforms = channel_config.transforms.len(), "Transform scripts compiled.");
+            dispatcher = dispatcher.with_transforms(transforms);
+        }
+        dispatcher = dispatcher.with_channel_config(channel_config);
+    }
+
+    if let Some(dir) = &config.schema_dir {
+        let schemas = Schemas::load(Path::new(dir)).expect("failed to load JSON schemas.");
+        dispatcher = dispatcher.with_schemas(schemas);
+    }
+
+    if let Some(producer) = setup_kafka_producer(&config).expect("failed to create Kafka producer.") {
+        dispatcher = dispatcher.with_kafka(producer);
+        info!("Kafka producer established.");
+    }
 
     // 3. This is your "Timer Function"
     info!(interval_ms = config.sweep_interval_ms, "Starting outbox sweeper timer...");
@@ -65,14 +103,13 @@ async fn run_sweeper_logic(config : Config) {
             _ = interval.tick() => {
                 // We clone the clients for the async task.
                 let db_pool_clone = db_pool.clone();
-                let sqs_client_clone = sqs_client.clone();
-                let sns_client_clone = sns_client.clone();
-                let batch_size = config.batch_size.clone();
+                let dispatcher_clone = dispatcher.clone();
+                let batch_size = config.batch_size;
 
                 tokio::spawn(async move {
                     if let Err(e) =
                         // The core logic is now called from its own module
-                        sweep_outbox_and_send(&db_pool_clone, &sqs_client_clone, &sns_client_clone, &batch_size).await
+                        sweep_outbox_and_send(&db_pool_clone, &dispatcher_clone, &batch_size).await
                     {
                         error!("Error during outbox sweep: {}", e);
                     }
@@ -104,6 +141,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }));
         let sentry_layer = sentry::integrations::tracing::layer();
         let fmt_layer = fmt::layer()
+            .with_writer(std::io::stderr)
             .with_target(false) // Don't log module paths
             .pretty();
         tracing_subscriber::registry()
@@ -114,24 +152,31 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("Sentry initialized with DSN.");
     }
 
+    // Shared between the sweeper and the status endpoint
+    let circuit_breakers = web::Data::new(CircuitBreakers::default());
+
+    let sweeper_circuit_breakers = circuit_breakers.clone().into_inner();
     let sweeper_handle = tokio::spawn(async move {
-        run_sweeper_logic(config).await;
+        run_sweeper_logic(config, sweeper_circuit_breakers).await;
     });
 
     // Spawn the health check server
-    let health_server = HttpServer::new(|| {
-        App::new().service(health_check)
+    let health_server = HttpServer::new(move || {
+        App::new()
+            .app_data(circuit_breakers.clone())
+            .service(health_check)
+            .service(status)
     })
     .bind(("0.0.0.0", 8080))? // Binds to all interfaces on port 8080
     .run();
 
-    println!("Health check server running on http://0.0.0.0:8080");
+    info!("Health check server running on http://0.0.0.0:8080");
 
     // Keep both tasks running
     // This will error out if either the server or your sweeper task fails
     let _ = tokio::try_join!(
-        async { health_server.await },
-        async { sweeper_handle.await.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e)) }
+        health_server,
+        async { sweeper_handle.await.map_err(std::io::Error::other) }
     )?;
 
     Ok(())
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use lapin::options::{BasicPublishOptions, ConfirmSelectOptions};
+use lapin::types::{AMQPValue, FieldTable};
+use lapin::{BasicProperties, Confirmation, Connection, ConnectionProperties};
+use tokio::sync::Mutex;
+use tracing::{info, instrument};
+use crate::config::ChannelSettings;
+use crate::messaging::cloudevents;
+use crate::messaging::{DispatchOutcome, FailedMessage};
+use crate::models::OutboxMessage;
+
+/// The exchange used when a channel does not configure one.
+const DEFAULT_EXCHANGE: &str = "amq.topic";
+
+/// Publishes to RabbitMQ, keeping one connection and confirm channel open per broker URI.
+///
+/// Connections are opened the first time a URI is used and reused by every later sweep. A
+/// connection that has dropped is replaced on the next dispatch.
+#[derive(Default)]
+pub struct AmqpTransport {
+    channels: Mutex<HashMap<String, (Connection, lapin::Channel)>>,
+}
+
+impl AmqpTransport {
+    /// Returns the open channel for the URI, connecting if there is not one yet.
+    async fn channel(&self, uri: &str) -> Result<lapin::Channel, lapin::Error> {
+        let mut channels = self.channels.lock().await;
+
+        if let Some((connection, channel)) = channels.get(uri)
+            && connection.status().connected()
+            && channel.status().connected()
+        {
+            return Ok(channel.clone());
+        }
+
+        info!("Opening AMQP connection.");
+        let connection = Connection::connect(uri, ConnectionProperties::default()).await?;
+        let channel = connection.create_channel().await?;
+        channel.confirm_select(ConfirmSelectOptions::default()).await?;
+
+        channels.insert(uri.to_string(), (connection, channel.clone()));
+        Ok(channel)
+    }
+
+    /// Publishes each message to the channel's exchange, using its `message_type` as the routing key.
+    ///
+    /// Messages are published as mandatory and persistent, and only count as sent once the broker
+    /// has confirmed them. A message the broker returns as unroutable is retried.
+    #[instrument(skip_all)]
+    pub async fn send_messages(
+        &self,
+        uri: &str,
+        settings: &ChannelSettings,
+        messages: &[OutboxMessage],
+    ) -> Result<DispatchOutcome, lapin::Error> {
+        let channel = self.channel(uri).await?;
+        let exchange = settings.exchange.as_deref().unwrap_or(DEFAULT_EXCHANGE);
+
+        let mut outcome = DispatchOutcome::default();
+        let mut confirms = Vec::with_capacity(messages.len());
+
+        for msg in messages {
+            let publish = channel
+                .basic_publish(
+                    exchange.into(),
+                    msg.message_type.as_str().into(),
+                    BasicPublishOptions { mandatory: true, ..BasicPublishOptions::default() },
+                    msg.body.as_bytes(),
+                    message_properties(settings, msg),
+                )
+                .await;
+
+            match publish {
+                Ok(confirm) => confirms.push((msg.id, confirm)),
+                Err(e) => outcome.failed.push(FailedMessage::retryable(msg.id, e.to_string())),
+            }
+        }
+
+        for (id, confirm) in confirms {
+            match confirmed(id, confirm.await) {
+                Ok(()) => outcome.sent.push(id),
+                Err(failed) => outcome.failed.push(failed),
+            }
+        }
+
+        Ok(outcome)
+    }
+}
+
+/// Maps the broker's confirmation of a publish onto the message, which only counts as sent when it
+/// was acked without being returned as unroutable.
+fn confirmed(id: i64, confirmation: Result<Confirmation, lapin::Error>) -> Result<(), FailedMessage> {
+    match confirmation {
+        Ok(Confirmation::Ack(None)) => Ok(()),
+        Ok(Confirmation::Ack(Some(returned))) => Err(FailedMessage::retryable(id, format!("message was unroutable: {}", returned.reply_text))),
+        Ok(Confirmation::Nack(_)) => Err(FailedMessage::retryable(id, "broker nacked the message")),
+        Ok(Confirmation::NotRequested) => Err(FailedMessage::retryable(id, "publisher confirms are not enabled")),
+        Err(e) => Err(FailedMessage::retryable(id, e.to_string())),
+    }
+}
+
+fn message_properties(settings: &ChannelSettings, msg: &OutboxMessage) -> BasicProperties {
+    let mut headers = FieldTable::default();
+    if let Some(trace_parent) = &msg.trace_parent {
+        headers.insert("traceparent".into(), AMQPValue::LongString(trace_parent.as_str().into()));
+    }
+    for (name, value) in cloudevents::binary_attributes(settings, msg) {
+        headers.insert(format!("cloudEvents:{name}").into(), AMQPValue::LongString(value.into()));
+    }
+
+    BasicProperties::default()
+        .with_message_id(msg.message_id.as_str().into())
+        .with_type(msg.message_type.as_str().into())
+        .with_content_type("application/json".into())
+        .with_delivery_mode(2)
+        .with_timestamp(msg.timestamp.timestamp() as u64)
+        .with_headers(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lapin::message::{BasicReturnMessage, Delivery};
+
+    fn returned(reply_text: &str) -> BasicReturnMessage {
+        BasicReturnMessage {
+            delivery: Delivery::mock(1, "amq.topic".into(), "test.topic".into(), false, Vec::new()),
+            reply_code: 312,
+            reply_text: reply_text.into(),
+        }
+    }
+
+    #[test]
+    fn test_only_acked_and_routed_messages_are_sent() {
+        assert_eq!(confirmed(1, Ok(Confirmation::Ack(None))), Ok(()));
+        assert_eq!(confirmed(2, Ok(Confirmation::Ack(Some(returned("NO_ROUTE"))))), Err(FailedMessage::retryable(2, "message was unroutable: NO_ROUTE")));
+        assert_eq!(confirmed(3, Ok(Confirmation::Nack(None))), Err(FailedMessage::retryable(3, "broker nacked the message")));
+        assert_eq!(confirmed(4, Ok(Confirmation::NotRequested)), Err(FailedMessage::retryable(4, "publisher confirms are not enabled")));
+    }
+
+    #[test]
+    fn test_confirm_errors_are_retried() {
+        let failed = confirmed(1, Err(lapin::ErrorKind::ChannelsLimitReached.into())).unwrap_err();
+
+        assert_eq!(failed.id, 1);
+        assert!(!failed.permanent, "A lost confirmation should be retried");
+    }
+
+    #[test]
+    fn test_message_properties_carry_the_outbox_columns() {
+        let msg = OutboxMessage::for_test(1, "amqp://localhost");
+        let settings = ChannelSettings { cloud_events: Some(cloudevents::CloudEventsMode::Binary), ..Default::default() };
+
+        let properties = message_properties(&settings, &msg);
+
+        assert_eq!(properties.message_id().as_ref().map(|id| id.as_str()), Some("message-1"));
+        assert_eq!(properties.delivery_mode(), &Some(2), "Messages should be persistent");
+        let headers = properties.headers().as_ref().unwrap();
+        assert_eq!(headers.inner().get("cloudEvents:id"), Some(&AMQPValue::LongString("message-1".into())));
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use aws_sdk_sns::types::MessageAttributeValue as SnsMessageAttributeValue;
+use aws_sdk_sqs::primitives::Blob;
+use aws_sdk_sqs::types::MessageAttributeValue as SqsMessageAttributeValue;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde_json::Value;
+use crate::messaging::{DispatchOutcome, FailedMessage};
+use crate::models::{OutboxColumn, OutboxMessage};
+
+/// SQS and SNS both reject a message with more than this many attributes.
+pub const MAX_MESSAGE_ATTRIBUTES: usize = 10;
+
+/// A message attribute built from one entry of the `headers` column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageAttribute {
+    /// `String`, `Number` or `Binary`, optionally followed by a `.custom-label`.
+    pub data_type: String,
+    pub value: AttributeValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeValue {
+    String(String),
+    Binary(Vec<u8>),
+}
+
+impl MessageAttribute {
+    pub fn string(value: impl Into<String>) -> Self {
+        Self { data_type: "String".to_string(), value: AttributeValue::String(value.into()) }
+    }
+}
+
+/// Why the headers of a message can not be sent as message attributes.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AttributeError {
+    #[error("headers must be a JSON object")]
+    NotAnObject,
+    #[error("message has {0} attributes, at most {MAX_MESSAGE_ATTRIBUTES} are allowed")]
+    TooMany(usize),
+    #[error("header {0:?} must be a string, number, boolean or a {{\"type\", \"value\"}} object")]
+    InvalidValue(String),
+    #[error("header {0:?} has the type {1:?}, it must be String, Number or Binary")]
+    UnknownType(String, String),
+    #[error("header {0:?} is a Binary attribute but its value is not base64")]
+    InvalidBinary(String),
+}
+
+/// Builds the message attributes for a message from its `headers` column and the given columns.
+///
+/// Strings and booleans become `String` attributes and numbers become `Number` attributes. A
+/// header can declare its type instead as `{"type": "Binary", "value": "<base64>"}`, the type may
+/// carry a custom label such as `Number.int`. Each column that is set is added as a `String`
+/// attribute named after the column, unless a header already has that name.
+pub fn message_attributes(msg: &OutboxMessage, columns: &[OutboxColumn]) -> Result<Vec<(String, MessageAttribute)>, AttributeError> {
+    let mut attributes = match &msg.headers {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Object(headers)) => headers
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), header_attribute(name, value)?)))
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => return Err(AttributeError::NotAnObject),
+    };
+
+    for column in columns {
+        if let Some(value) = msg.column(*column)
+            && !attributes.iter().any(|(name, _)| name == column.name())
+        {
+            attributes.push((column.name().to_string(), MessageAttribute::string(value)));
+        }
+    }
+
+    if attributes.len() > MAX_MESSAGE_ATTRIBUTES {
+        return Err(AttributeError::TooMany(attributes.len()));
+    }
+    Ok(attributes)
+}
+
+fn header_attribute(name: &str, value: &Value) -> Result<MessageAttribute, AttributeError> {
+    match value {
+        Value::String(value) => Ok(MessageAttribute::string(value.clone())),
+        Value::Bool(value) => Ok(MessageAttribute::string(value.to_string())),
+        Value::Number(value) => Ok(MessageAttribute {
+            data_type: "Number".to_string(),
+            value: AttributeValue::String(value.to_string()),
+        }),
+        Value::Object(declared) => {
+            let (Some(Value::String(data_type)), Some(value), 2) = (declared.get("type"), declared.get("value"), declared.len()) else {
+                return Err(AttributeError::InvalidValue(name.to_string()));
+            };
+            let value = match (data_type.split('.').next().unwrap_or_default(), value) {
+                ("Binary", Value::String(value)) => {
+                    AttributeValue::Binary(BASE64.decode(value).map_err(|_| AttributeError::InvalidBinary(name.to_string()))?)
+                }
+                ("String" | "Number", Value::String(value)) => AttributeValue::String(value.clone()),
+                ("String" | "Number", Value::Number(value)) => AttributeValue::String(value.to_string()),
+                ("String" | "Number" | "Binary", _) => return Err(AttributeError::InvalidValue(name.to_string())),
+                _ => return Err(AttributeError::UnknownType(name.to_string(), data_type.clone())),
+            };
+            Ok(MessageAttribute { data_type: data_type.clone(), value })
+        }
+        Value::Null | Value::Array(_) => Err(AttributeError::InvalidValue(name.to_string())),
+    }
+}
+
+/// Pairs each message with its attributes, reporting any message whose headers can not be sent
+/// as a permanent failure.
+pub(crate) fn with_message_attributes<'m>(
+    messages: &'m [OutboxMessage],
+    columns: &[OutboxColumn],
+    outcome: &mut DispatchOutcome,
+) -> Vec<(&'m OutboxMessage, Vec<(String, MessageAttribute)>)> {
+    messages
+        .iter()
+        .filter_map(|msg| match message_attributes(msg, columns) {
+            Ok(attributes) => Some((msg, attributes)),
+            Err(e) => {
+                outcome.failed.push(FailedMessage::permanent(msg.id, e.to_string()));
+                None
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn to_sqs_attributes(attributes: &[(String, MessageAttribute)]) -> Option<HashMap<String, SqsMessageAttributeValue>> {
+    if attributes.is_empty() {
+        return None;
+    }
+    Some(attributes.iter().map(|(name, attribute)| {
+        let builder = SqsMessageAttributeValue::builder().data_type(&attribute.data_type);
+        let builder = match &attribute.value {
+            AttributeValue::String(value) => builder.string_value(value),
+            AttributeValue::Binary(value) => builder.binary_value(Blob::new(value.clone())),
+        };
+        (name.clone(), builder.build().expect("data_type is always set"))
+    }).collect())
+}
+
+pub(crate) fn to_sns_attributes(attributes: &[(String, MessageAttribute)]) -> Option<HashMap<String, SnsMessageAttributeValue>> {
+    if attributes.is_empty() {
+        return None;
+    }
+    Some(attributes.iter().map(|(name, attribute)| {
+        let builder = SnsMessageAttributeValue::builder().data_type(&attribute.data_type);
+        let builder = match &attribute.value {
+            AttributeValue::String(value) => builder.string_value(value),
+            AttributeValue::Binary(value) => builder.binary_value(aws_sdk_sns::primitives::Blob::new(value.clone())),
+        };
+        (name.clone(), builder.build().expect("data_type is always set"))
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn with_headers(headers: Value) -> OutboxMessage {
+        OutboxMessage {
+            headers: Some(headers),
+            ..OutboxMessage::for_test(1, "")
+        }
+    }
+
+    #[test]
+    fn test_message_attributes_are_inferred_or_declared() {
+        let msg = with_headers(json!({
+            "tenant_id": "acme",
+            "schema_version": 3,
+            "replayed": false,
+            "amount": { "type": "Number.decimal", "value": "12.50" },
+            "signature": { "type": "Binary", "value": "aGVsbG8=" },
+        }));
+
+        let attributes = message_attributes(&msg, &[]).unwrap();
+
+        assert_eq!(attributes, vec![
+            ("amount".to_string(), MessageAttribute { data_type: "Number.decimal".to_string(), value: AttributeValue::String("12.50".to_string()) }),
+            ("replayed".to_string(), MessageAttribute::string("false")),
+            ("schema_version".to_string(), MessageAttribute { data_type: "Number".to_string(), value: AttributeValue::String("3".to_string()) }),
+            ("signature".to_string(), MessageAttribute { data_type: "Binary".to_string(), value: AttributeValue::Binary(b"hello".to_vec()) }),
+            ("tenant_id".to_string(), MessageAttribute::string("acme")),
+        ]);
+    }
+
+    #[test]
+    fn test_columns_are_added_as_string_attributes() {
+        let msg = OutboxMessage {
+            headers: Some(json!({ "message_type": "order.overridden", "tenant_id": "acme" })),
+            ..OutboxMessage::for_test(1, "")
+        };
+        let columns = [OutboxColumn::MessageType, OutboxColumn::PartitionKey, OutboxColumn::MessageId];
+
+        let attributes = message_attributes(&msg, &columns).unwrap();
+
+        assert_eq!(attributes, vec![
+            ("message_type".to_string(), MessageAttribute::string("order.overridden")),
+            ("tenant_id".to_string(), MessageAttribute::string("acme")),
+            ("message_id".to_string(), MessageAttribute::string("message-1")),
+        ], "Headers should take precedence and unset columns should be skipped");
+
+        let nine_headers: serde_json::Map<String, Value> = (0..9).map(|i| (format!("header_{i}"), json!(i))).collect();
+        let msg = with_headers(Value::Object(nine_headers));
+        assert_eq!(message_attributes(&msg, &columns), Err(AttributeError::TooMany(11)), "Columns should count towards the limit");
+    }
+
+    #[test]
+    fn test_invalid_headers_are_rejected() {
+        let too_many: serde_json::Map<String, Value> = (0..11).map(|i| (format!("header_{i}"), json!(i))).collect();
+        let cases = vec![
+            (json!(["tenant_id"]), AttributeError::NotAnObject),
+            (Value::Object(too_many), AttributeError::TooMany(11)),
+            (json!({ "tags": ["a", "b"] }), AttributeError::InvalidValue("tags".to_string())),
+            (json!({ "tenant": { "type": "Date", "value": "today" } }), AttributeError::UnknownType("tenant".to_string(), "Date".to_string())),
+            (json!({ "signature": { "type": "Binary", "value": "not base64!" } }), AttributeError::InvalidBinary("signature".to_string())),
+        ];
+
+        for (headers, expected) in cases {
+            assert_eq!(message_attributes(&with_headers(headers.clone()), &[]), Err(expected), "{headers}");
+        }
+    }
+
+    #[test]
+    fn test_messages_with_invalid_headers_are_permanent_failures() {
+        let messages = vec![OutboxMessage::for_test(1, ""), OutboxMessage { id: 2, ..with_headers(json!("tenant_id")) }];
+        let mut outcome = DispatchOutcome::default();
+
+        let valid = with_message_attributes(&messages, &[], &mut outcome);
+
+        assert_eq!(valid.iter().map(|(msg, _)| msg.id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(outcome.failed, vec![FailedMessage::permanent(2, "headers must be a JSON object")]);
+    }
+}
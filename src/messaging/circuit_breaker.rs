@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::config::ChannelSettings;
+
+/// How many calls in a row must fail before a circuit opens, when a channel does not set one.
+const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+/// How long a circuit stays open before a trial call is let through, when a channel does not set one.
+const DEFAULT_COOLDOWN_MS: u64 = 30_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Calls are made as normal.
+    Closed,
+    /// Calls are skipped until the cooldown has passed.
+    Open,
+    /// A single trial call is in flight, which closes the circuit if it succeeds.
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_until: Option<Instant>,
+}
+
+/// A circuit as shown on the status endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CircuitStatus {
+    pub channel_address: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    /// How long until an open circuit lets a trial call through.
+    pub retry_in_ms: Option<u64>,
+}
+
+/// A circuit breaker for every channel address the sweeper has sent to.
+///
+/// A circuit opens once `circuit_failure_threshold` calls in a row have failed, and stays open
+/// for `circuit_cooldown_ms`. After that one trial call is let through: if it succeeds the circuit
+/// closes, and if it fails the circuit opens again for another cooldown.
+#[derive(Debug, Default)]
+pub struct CircuitBreakers {
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl CircuitBreakers {
+    /// Whether a call to the channel should be made now.
+    pub fn allow(&self, channel_address: &str) -> bool {
+        self.allow_at(channel_address, Instant::now())
+    }
+
+    fn allow_at(&self, channel_address: &str, now: Instant) -> bool {
+        let mut circuits = self.circuits.lock().expect("circuit breaker lock was poisoned");
+        let Some(circuit) = circuits.get_mut(channel_address) else {
+            return true;
+        };
+        match circuit.state {
+            CircuitState::Closed => true,
+            CircuitState::HalfOpen => false,
+            CircuitState::Open => {
+                let cooled_down = circuit.opened_until.is_none_or(|until| now >= until);
+                if cooled_down {
+                    circuit.state = CircuitState::HalfOpen;
+                    circuit.opened_until = None;
+                }
+                cooled_down
+            }
+        }
+    }
+
+    /// Closes the channel's circuit after a call succeeds, returning true when it was not already closed.
+    pub fn record_success(&self, channel_address: &str) -> bool {
+        let mut circuits = self.circuits.lock().expect("circuit breaker lock was poisoned");
+        let closed = Circuit { state: CircuitState::Closed, consecutive_failures: 0, opened_until: None };
+        circuits.insert(channel_address.to_string(), closed).is_some_and(|circuit| circuit.state != CircuitState::Closed)
+    }
+
+    /// Counts a failed call to the channel, returning true when this failure opened its circuit.
+    pub fn record_failure(&self, channel_address: &str, settings: &ChannelSettings) -> bool {
+        self.record_failure_at(channel_address, settings, Instant::now())
+    }
+
+    fn record_failure_at(&self, channel_address: &str, settings: &ChannelSettings, now: Instant) -> bool {
+        let mut circuits = self.circuits.lock().expect("circuit breaker lock was poisoned");
+        let circuit = circuits.entry(channel_address.to_string()).or_insert(Circuit {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            opened_until: None,
+        });
+        circuit.consecutive_failures += 1;
+
+        let threshold = settings.circuit_failure_threshold.unwrap_or(DEFAULT_FAILURE_THRESHOLD);
+        let opens = match circuit.state {
+            CircuitState::Closed => circuit.consecutive_failures >= threshold,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if opens {
+            let cooldown = Duration::from_millis(settings.circuit_cooldown_ms.unwrap_or(DEFAULT_COOLDOWN_MS));
+            circuit.state = CircuitState::Open;
+            circuit.opened_until = Some(now + cooldown);
+        }
+        opens
+    }
+
+    /// The state of every circuit, sorted by channel address.
+    pub fn status(&self) -> Vec<CircuitStatus> {
+        let now = Instant::now();
+        let circuits = self.circuits.lock().expect("circuit breaker lock was poisoned");
+        let mut status: Vec<CircuitStatus> = circuits.iter().map(|(channel_address, circuit)| CircuitStatus {
+            channel_address: channel_address.clone(),
+            state: circuit.state,
+            consecutive_failures: circuit.consecutive_failures,
+            retry_in_ms: circuit.opened_until.map(|until| until.saturating_duration_since(now).as_millis() as u64),
+        }).collect();
+        status.sort_by(|a, b| a.channel_address.cmp(&b.channel_address));
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(breakers: &CircuitBreakers, channel_address: &str) -> Option<CircuitState> {
+        breakers.status().into_iter().find(|c| c.channel_address == channel_address).map(|c| c.state)
+    }
+
+    #[test]
+    fn test_circuit_opens_after_the_threshold_and_recovers() {
+        let breakers = CircuitBreakers::default();
+        let settings = ChannelSettings { circuit_failure_threshold: Some(2), circuit_cooldown_ms: Some(1000), ..Default::default() };
+        let start = Instant::now();
+
+        assert!(breakers.allow_at("memory://queue", start));
+        assert!(!breakers.record_failure_at("memory://queue", &settings, start));
+        assert!(breakers.record_failure_at("memory://queue", &settings, start), "The second failure should open the circuit");
+        assert!(!breakers.allow_at("memory://queue", start + Duration::from_millis(999)));
+
+        assert!(breakers.allow_at("memory://queue", start + Duration::from_secs(1)), "A trial call should be let through after the cooldown");
+        assert_eq!(state(&breakers, "memory://queue"), Some(CircuitState::HalfOpen));
+        assert!(!breakers.allow_at("memory://queue", start + Duration::from_secs(1)), "Only one trial call should be let through");
+
+        assert!(breakers.record_success("memory://queue"), "The successful trial should close the circuit");
+        assert_eq!(state(&breakers, "memory://queue"), Some(CircuitState::Closed));
+        assert!(breakers.allow_at("memory://queue", start + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_failed_trial_reopens_the_circuit() {
+        let breakers = CircuitBreakers::default();
+        let settings = ChannelSettings { circuit_failure_threshold: Some(1), circuit_cooldown_ms: Some(1000), ..Default::default() };
+        let start = Instant::now();
+
+        breakers.record_failure_at("memory://queue", &settings, start);
+        assert!(breakers.allow_at("memory://queue", start + Duration::from_secs(1)));
+        assert!(breakers.record_failure_at("memory://queue", &settings, start + Duration::from_secs(1)));
+
+        assert!(!breakers.allow_at("memory://queue", start + Duration::from_millis(1500)), "The cooldown should start again");
+        assert!(breakers.allow_at("memory://queue", start + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_successes_reset_the_failure_count() {
+        let breakers = CircuitBreakers::default();
+        let settings = ChannelSettings { circuit_failure_threshold: Some(2), ..Default::default() };
+        let start = Instant::now();
+
+        breakers.record_failure_at("memory://queue", &settings, start);
+        breakers.record_success("memory://queue");
+        assert!(!breakers.record_failure_at("memory://queue", &settings, start), "Failures should have to be consecutive");
+        assert!(breakers.allow_at("memory://queue", start));
+    }
+}
@@ -0,0 +1,210 @@
+use std::borrow::Cow;
+use aws_sdk_s3::Client as S3Client;
+use aws_sdk_s3::error::DisplayErrorContext;
+use aws_sdk_s3::primitives::ByteStream;
+use serde_json::{json, Value};
+use tracing::{info, instrument};
+use crate::messaging::attributes::{message_attributes, AttributeValue};
+use crate::messaging::{DispatchOutcome, FailedMessage};
+use crate::models::{OutboxColumn, OutboxMessage};
+
+/// SQS and SNS reject a message larger than this, counting its body and attributes.
+pub const MAX_MESSAGE_BYTES: usize = 256 * 1024;
+
+/// The attribute the Extended Client sets to the size of an offloaded body.
+pub const EXTENDED_PAYLOAD_SIZE_ATTRIBUTE: &str = "ExtendedPayloadSize";
+
+/// The class name the Extended Client expects at the start of a pointer message.
+const S3_POINTER_CLASS: &str = "software.amazon.payloadoffloading.PayloadS3Pointer";
+
+/// Stores the body of every message that is too large to send in the S3 bucket, replacing it
+/// with a pointer to the object.
+///
+/// The pointer and `ExtendedPayloadSize` attribute follow the format of the Amazon SQS and SNS
+/// Extended Clients, so their consumers fetch the body from S3 transparently. Objects are keyed
+/// by `message_id`, so a message that is offloaded again overwrites its earlier object. A message
+/// whose body can not be stored is reported as a retryable failure and left out of the result. A
+/// message with no room left for the `ExtendedPayloadSize` attribute is reported as a permanent
+/// failure before anything is stored, so no object is left behind for it.
+#[instrument(skip(s3_client, columns, messages, outcome))]
+pub async fn offload_large_payloads<'m>(
+    s3_client: &S3Client,
+    bucket: &str,
+    columns: &[OutboxColumn],
+    messages: &'m [OutboxMessage],
+    outcome: &mut DispatchOutcome,
+) -> Cow<'m, [OutboxMessage]> {
+    if messages.iter().all(|msg| message_size(msg, columns) <= MAX_MESSAGE_BYTES) {
+        return Cow::Borrowed(messages);
+    }
+
+    let mut offloaded = Vec::with_capacity(messages.len());
+    for msg in messages {
+        if message_size(msg, columns) <= MAX_MESSAGE_BYTES {
+            offloaded.push(msg.clone());
+            continue;
+        }
+
+        let pointer = pointer_message(msg, bucket);
+        if let Err(e) = message_attributes(&pointer, columns) {
+            outcome.failed.push(FailedMessage::permanent(msg.id, e.to_string()));
+            continue;
+        }
+
+        let stored = s3_client
+            .put_object()
+            .bucket(bucket)
+            .key(&msg.message_id)
+            .body(ByteStream::from(msg.body.clone().into_bytes()))
+            .send()
+            .await;
+        match stored {
+            Ok(_) => {
+                info!(id = msg.id, bytes = msg.body.len(), "Offloaded message body to S3.");
+                offloaded.push(pointer);
+            }
+            Err(e) => outcome.failed.push(FailedMessage::retryable(msg.id, format!("failed to offload the body to S3: {}", DisplayErrorContext(e)))),
+        }
+    }
+    Cow::Owned(offloaded)
+}
+
+/// The size SQS and SNS count for a message, its body plus the name, type and value of each attribute.
+fn message_size(msg: &OutboxMessage, columns: &[OutboxColumn]) -> usize {
+    let attributes = message_attributes(msg, columns).unwrap_or_default();
+    msg.body.len()
+        + attributes.iter().map(|(name, attribute)| {
+            let value_size = match &attribute.value {
+                AttributeValue::String(value) => value.len(),
+                AttributeValue::Binary(value) => value.len(),
+            };
+            name.len() + attribute.data_type.len() + value_size
+        }).sum::<usize>()
+}
+
+/// Replaces the body with an Extended Client pointer to the S3 object holding it.
+fn pointer_message(msg: &OutboxMessage, bucket: &str) -> OutboxMessage {
+    let mut pointer = msg.clone();
+    pointer.body = json!([S3_POINTER_CLASS, { "s3BucketName": bucket, "s3Key": msg.message_id }]).to_string();
+
+    let payload_size = json!({ "type": "Number", "value": msg.body.len().to_string() });
+    match &mut pointer.headers {
+        Some(Value::Object(headers)) => {
+            headers.insert(EXTENDED_PAYLOAD_SIZE_ATTRIBUTE.to_string(), payload_size);
+        }
+        headers @ (None | Some(Value::Null)) => *headers = Some(json!({ EXTENDED_PAYLOAD_SIZE_ATTRIBUTE: payload_size })),
+        // Headers that are not an object will be dead-lettered when the attributes are built
+        Some(_) => {}
+    }
+    pointer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpResponse, HttpServer, web};
+    use aws_config::{BehaviorVersion, Region};
+    use aws_sdk_s3::config::Credentials;
+    use std::sync::{Arc, Mutex};
+
+    type Stored = Arc<Mutex<Vec<(String, String, String)>>>;
+
+    async fn put_object(path: web::Path<(String, String)>, body: String, stored: web::Data<Stored>) -> HttpResponse {
+        let (bucket, key) = path.into_inner();
+        if bucket == "unavailable" {
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+        stored.lock().unwrap().push((bucket, key, body));
+        HttpResponse::Ok().insert_header(("ETag", "\"etag\"")).finish()
+    }
+
+    /// Starts a local stand-in for S3 that keeps every object put into it.
+    fn start_s3() -> (S3Client, Stored) {
+        let stored: Stored = Arc::default();
+        let data = web::Data::new(stored.clone());
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .app_data(web::PayloadConfig::new(2 * MAX_MESSAGE_BYTES))
+                .route("/{bucket}/{key}", web::put().to(put_object))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .expect("Failed to bind test server");
+        let endpoint_url = format!("http://{}", server.addrs()[0]);
+        tokio::spawn(server.run());
+
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("eu-west-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .endpoint_url(endpoint_url)
+            .force_path_style(true)
+            .build();
+        (S3Client::from_conf(config), stored)
+    }
+
+    fn large_message(id: i64) -> OutboxMessage {
+        OutboxMessage {
+            body: format!(r#"{{ "padding": "{}" }}"#, "x".repeat(MAX_MESSAGE_BYTES)),
+            ..OutboxMessage::for_test(id, "")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_bodies_are_offloaded() {
+        // --- ARRANGE ---
+        let (s3_client, stored) = start_s3();
+        let messages = vec![OutboxMessage::for_test(1, ""), large_message(2)];
+        let mut outcome = DispatchOutcome::default();
+
+        // --- ACT ---
+        let offloaded = offload_large_payloads(&s3_client, "payloads", &[], &messages, &mut outcome).await;
+
+        // --- ASSERT ---
+        assert!(outcome.failed.is_empty(), "Unexpected failures: {:?}", outcome.failed);
+        assert_eq!(offloaded[0].body, messages[0].body, "Small bodies should be sent as they are");
+        assert_eq!(
+            serde_json::from_str::<Value>(&offloaded[1].body).unwrap(),
+            json!(["software.amazon.payloadoffloading.PayloadS3Pointer", { "s3BucketName": "payloads", "s3Key": "message-2" }])
+        );
+        assert_eq!(
+            offloaded[1].headers,
+            Some(json!({ "ExtendedPayloadSize": { "type": "Number", "value": messages[1].body.len().to_string() } }))
+        );
+
+        let stored = stored.lock().unwrap();
+        assert_eq!(stored.len(), 1, "Only the large body should be stored");
+        assert_eq!(stored[0], ("payloads".to_string(), "message-2".to_string(), messages[1].body.clone()));
+    }
+
+    #[tokio::test]
+    async fn test_small_bodies_are_not_copied_and_failed_uploads_are_retried() {
+        let (s3_client, _stored) = start_s3();
+        let small = vec![OutboxMessage::for_test(1, "")];
+        let mut outcome = DispatchOutcome::default();
+
+        let offloaded = offload_large_payloads(&s3_client, "unavailable", &[], &small, &mut outcome).await;
+        assert!(matches!(offloaded, Cow::Borrowed(_)), "Nothing should be copied when no body is too large");
+
+        let large = vec![large_message(2)];
+        let offloaded = offload_large_payloads(&s3_client, "unavailable", &[], &large, &mut outcome).await;
+        assert!(offloaded.is_empty(), "A body that could not be stored should not be sent");
+        assert_eq!(outcome.failed.len(), 1);
+        assert!(!outcome.failed[0].permanent, "A failed upload should be retried");
+    }
+
+    #[tokio::test]
+    async fn test_messages_without_room_for_the_size_attribute_are_not_stored() {
+        let (s3_client, stored) = start_s3();
+        let headers: serde_json::Map<String, Value> = (1..=10).map(|n| (format!("header_{n}"), json!(n))).collect();
+        let messages = vec![OutboxMessage { headers: Some(Value::Object(headers)), ..large_message(1) }];
+        let mut outcome = DispatchOutcome::default();
+
+        let offloaded = offload_large_payloads(&s3_client, "payloads", &[], &messages, &mut outcome).await;
+
+        assert!(offloaded.is_empty());
+        assert_eq!(outcome.failed, vec![FailedMessage::permanent(1, "message has 11 attributes, at most 10 are allowed")]);
+        assert!(stored.lock().unwrap().is_empty(), "The body should not have been stored");
+    }
+}
@@ -0,0 +1,166 @@
+use std::borrow::Cow;
+use chrono::SecondsFormat;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::config::ChannelSettings;
+use crate::models::OutboxMessage;
+
+/// The version of the CloudEvents spec events are produced for.
+const SPEC_VERSION: &str = "1.0";
+/// The most event attributes binary mode adds to a message, which is when it has a trace parent.
+pub const MAX_BINARY_ATTRIBUTES: usize = 6;
+
+/// How a channel's messages are turned into CloudEvents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CloudEventsMode {
+    /// The body is wrapped in a JSON envelope holding the event attributes.
+    Structured,
+    /// The body is sent as it is and the event attributes are sent as transport headers.
+    Binary,
+}
+
+/// Wraps every body in a structured mode CloudEvents envelope, if the channel asks for one.
+///
+/// A body that is valid JSON is embedded as the event `data`, anything else is embedded as a
+/// string with a `text/plain` content type.
+pub fn to_structured<'m>(settings: &ChannelSettings, messages: &'m [OutboxMessage]) -> Cow<'m, [OutboxMessage]> {
+    if settings.cloud_events != Some(CloudEventsMode::Structured) {
+        return Cow::Borrowed(messages);
+    }
+
+    Cow::Owned(messages.iter().map(|msg| {
+        let (data, content_type) = match serde_json::from_str::<Value>(&msg.body) {
+            Ok(data) => (data, "application/json"),
+            Err(_) => (Value::String(msg.body.clone()), "text/plain"),
+        };
+
+        let mut envelope = json!({
+            "specversion": SPEC_VERSION,
+            "id": msg.message_id,
+            "type": msg.message_type,
+            "source": settings.source(),
+            "time": msg.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "datacontenttype": content_type,
+            "data": data,
+        });
+        if let Some(trace_parent) = &msg.trace_parent {
+            envelope["traceparent"] = json!(trace_parent);
+        }
+
+        OutboxMessage { body: envelope.to_string(), ..msg.clone() }
+    }).collect())
+}
+
+/// The event attributes to send as headers when the channel uses binary mode, without the
+/// prefix each transport's binding puts on them.
+pub fn binary_attributes(settings: &ChannelSettings, msg: &OutboxMessage) -> Vec<(&'static str, String)> {
+    if settings.cloud_events != Some(CloudEventsMode::Binary) {
+        return Vec::new();
+    }
+
+    let mut attributes = vec![
+        ("specversion", SPEC_VERSION.to_string()),
+        ("id", msg.message_id.clone()),
+        ("type", msg.message_type.clone()),
+        ("source", settings.source().to_string()),
+        ("time", msg.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)),
+    ];
+    if let Some(trace_parent) = &msg.trace_parent {
+        attributes.push(("traceparent", trace_parent.clone()));
+    }
+    attributes
+}
+
+/// Adds the binary mode event attributes to the `headers` of each message, with a `ce_` prefix,
+/// so that SQS and SNS send them as message attributes.
+pub fn with_binary_headers<'m>(settings: &ChannelSettings, messages: &'m [OutboxMessage]) -> Cow<'m, [OutboxMessage]> {
+    if settings.cloud_events != Some(CloudEventsMode::Binary) {
+        return Cow::Borrowed(messages);
+    }
+
+    Cow::Owned(messages.iter().map(|msg| {
+        let mut msg = msg.clone();
+        let attributes = binary_attributes(settings, &msg);
+        match &mut msg.headers {
+            Some(Value::Object(headers)) => {
+                headers.extend(attributes.into_iter().map(|(name, value)| (format!("ce_{name}"), json!(value))));
+            }
+            headers @ (None | Some(Value::Null)) => {
+                *headers = Some(Value::Object(attributes.into_iter().map(|(name, value)| (format!("ce_{name}"), json!(value))).collect()));
+            }
+            // Headers that are not an object will be dead-lettered when the attributes are built
+            Some(_) => {}
+        }
+        msg
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn test_message() -> OutboxMessage {
+        OutboxMessage {
+            timestamp: chrono::Utc.with_ymd_and_hms(2026, 3, 1, 12, 30, 0).unwrap(),
+            ..OutboxMessage::for_test(1, "")
+        }
+    }
+
+    #[test]
+    fn test_structured_envelope() {
+        let settings = ChannelSettings {
+            cloud_events: Some(CloudEventsMode::Structured),
+            source: Some("/orders".to_string()),
+            ..Default::default()
+        };
+        let messages = vec![test_message(), OutboxMessage { body: "not json".to_string(), trace_parent: None, ..test_message() }];
+
+        let events = to_structured(&settings, &messages);
+
+        assert_eq!(serde_json::from_str::<Value>(&events[0].body).unwrap(), json!({
+            "specversion": "1.0",
+            "id": "message-1",
+            "type": "test.topic",
+            "source": "/orders",
+            "time": "2026-03-01T12:30:00.000Z",
+            "datacontenttype": "application/json",
+            "data": { "id": 1 },
+            "traceparent": "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        }));
+
+        let event: Value = serde_json::from_str(&events[1].body).unwrap();
+        assert_eq!(event["data"], "not json");
+        assert_eq!(event["datacontenttype"], "text/plain");
+        assert_eq!(event.get("traceparent"), None);
+    }
+
+    #[test]
+    fn test_binary_headers() {
+        let settings = ChannelSettings { cloud_events: Some(CloudEventsMode::Binary), ..Default::default() };
+        let messages = vec![OutboxMessage { headers: Some(json!({ "tenant_id": "acme" })), ..test_message() }];
+
+        let events = with_binary_headers(&settings, &messages);
+
+        assert_eq!(events[0].body, messages[0].body, "Binary mode should not change the body");
+        assert_eq!(events[0].headers, Some(json!({
+            "tenant_id": "acme",
+            "ce_specversion": "1.0",
+            "ce_id": "message-1",
+            "ce_type": "test.topic",
+            "ce_source": "outbox-sweeper",
+            "ce_time": "2026-03-01T12:30:00.000Z",
+            "ce_traceparent": "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        })));
+    }
+
+    #[test]
+    fn test_nothing_is_copied_without_cloud_events() {
+        let messages = vec![test_message()];
+
+        assert!(matches!(to_structured(&ChannelSettings::default(), &messages), Cow::Borrowed(_)));
+        assert!(matches!(with_binary_headers(&ChannelSettings::default(), &messages), Cow::Borrowed(_)));
+        assert!(binary_attributes(&ChannelSettings::default(), &messages[0]).is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use std::borrow::Cow;
+use std::io::Write;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use flate2::write::GzEncoder;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::info;
+use crate::config::ChannelSettings;
+use crate::models::OutboxMessage;
+
+/// The attribute that tells consumers how a body was compressed.
+pub const CONTENT_ENCODING_ATTRIBUTE: &str = "content-encoding";
+
+/// Bodies no larger than this are sent as they are when a channel does not set a threshold.
+const DEFAULT_THRESHOLD_BYTES: usize = 1024;
+
+/// How a channel's bodies are compressed before they are base64 encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// The `content-encoding` value consumers see.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    fn compress(&self, body: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body).expect("writing to a Vec can not fail");
+                encoder.finish().expect("writing to a Vec can not fail")
+            }
+            Compression::Zstd => zstd::encode_all(body, zstd::DEFAULT_COMPRESSION_LEVEL).expect("reading from a slice can not fail"),
+        }
+    }
+}
+
+/// Compresses and base64 encodes every body over the channel's `compression_threshold_bytes`,
+/// adding a `content-encoding` header naming the compression used.
+///
+/// A body is only replaced when the encoded result is smaller than the original. The compression
+/// ratio of the batch is logged so it can be tracked.
+pub fn compress_bodies<'m>(settings: &ChannelSettings, messages: &'m [OutboxMessage]) -> Cow<'m, [OutboxMessage]> {
+    let Some(compression) = settings.compression else {
+        return Cow::Borrowed(messages);
+    };
+    let threshold = settings.compression_threshold_bytes.unwrap_or(DEFAULT_THRESHOLD_BYTES);
+    if messages.iter().all(|msg| msg.body.len() <= threshold) {
+        return Cow::Borrowed(messages);
+    }
+
+    let (mut bytes_before, mut bytes_after, mut messages_compressed) = (0, 0, 0);
+    let compressed = messages.iter().map(|msg| {
+        if msg.body.len() <= threshold || !matches!(msg.headers, None | Some(Value::Null | Value::Object(_))) {
+            return msg.clone();
+        }
+        let body = BASE64.encode(compression.compress(msg.body.as_bytes()));
+        if body.len() >= msg.body.len() {
+            return msg.clone();
+        }
+
+        bytes_before += msg.body.len();
+        bytes_after += body.len();
+        messages_compressed += 1;
+
+        let mut compressed = msg.clone();
+        compressed.body = body;
+        match &mut compressed.headers {
+            Some(Value::Object(headers)) => {
+                headers.insert(CONTENT_ENCODING_ATTRIBUTE.to_string(), json!(compression.name()));
+            }
+            headers => *headers = Some(json!({ CONTENT_ENCODING_ATTRIBUTE: compression.name() })),
+        }
+        compressed
+    }).collect();
+
+    if messages_compressed > 0 {
+        let compression_ratio = bytes_after as f64 / bytes_before as f64;
+        info!(compression = compression.name(), messages_compressed, bytes_before, bytes_after, compression_ratio, "Compressed message bodies.");
+    }
+    Cow::Owned(compressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn message_with_body(id: i64, body: String) -> OutboxMessage {
+        OutboxMessage { body, ..OutboxMessage::for_test(id, "") }
+    }
+
+    #[test]
+    fn test_bodies_over_the_threshold_are_compressed() {
+        let large = format!(r#"{{ "items": [{}] }}"#, vec![r#"{ "sku": "ABC-123", "quantity": 1 }"#; 100].join(","));
+        let messages = vec![OutboxMessage::for_test(1, ""), message_with_body(2, large.clone())];
+
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let settings = ChannelSettings { compression: Some(compression), compression_threshold_bytes: Some(100), ..Default::default() };
+
+            let compressed = compress_bodies(&settings, &messages);
+
+            assert_eq!(compressed[0].body, messages[0].body, "Bodies under the threshold should be sent as they are");
+            assert_eq!(compressed[0].headers, None);
+            assert_eq!(compressed[1].headers, Some(json!({ "content-encoding": compression.name() })));
+
+            let encoded = BASE64.decode(&compressed[1].body).unwrap();
+            let mut decoded = String::new();
+            match compression {
+                Compression::Gzip => flate2::read::GzDecoder::new(encoded.as_slice()).read_to_string(&mut decoded).unwrap(),
+                Compression::Zstd => zstd::Decoder::new(encoded.as_slice()).unwrap().read_to_string(&mut decoded).unwrap(),
+            };
+            assert_eq!(decoded, large, "{} body did not round trip", compression.name());
+        }
+    }
+
+    #[test]
+    fn test_nothing_is_copied_without_compression() {
+        let messages = vec![message_with_body(1, "x".repeat(2048))];
+
+        assert!(matches!(compress_bodies(&ChannelSettings::default(), &messages), Cow::Borrowed(_)));
+
+        let settings = ChannelSettings { compression: Some(Compression::Gzip), ..Default::default() };
+        assert!(matches!(compress_bodies(&settings, &[OutboxMessage::for_test(1, "")]), Cow::Borrowed(_)), "Small batches should not be copied");
+    }
+}
@@ -0,0 +1,217 @@
+use std::borrow::Cow;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::Aes256Gcm;
+use aws_sdk_kms::Client as KmsClient;
+use aws_sdk_kms::error::DisplayErrorContext;
+use aws_sdk_kms::types::DataKeySpec;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::instrument;
+use crate::messaging::{DispatchOutcome, FailedMessage};
+use crate::models::OutboxMessage;
+
+/// The attribute holding the id of the key that wrapped the data key.
+pub const KEY_ID_ATTRIBUTE: &str = "encryption-key-id";
+/// The attribute holding the base64 encoded data key, wrapped by the key provider.
+pub const ENCRYPTED_DATA_KEY_ATTRIBUTE: &str = "encryption-data-key";
+/// The attribute holding the base64 encoded nonce the body was encrypted with.
+pub const NONCE_ATTRIBUTE: &str = "encryption-nonce";
+/// Every attribute an encrypted message carries.
+pub const ATTRIBUTES: [&str; 3] = [KEY_ID_ATTRIBUTE, ENCRYPTED_DATA_KEY_ATTRIBUTE, NONCE_ATTRIBUTE];
+
+/// Where the key that wraps each data key comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase", deny_unknown_fields)]
+pub enum EncryptionSettings {
+    /// Data keys are generated and wrapped by KMS with `GenerateDataKey`.
+    Kms { key_id: String },
+    /// Data keys are wrapped locally with AES-256-GCM under a base64 encoded 32 byte key, for tests
+    /// and local development.
+    Static { key_id: String, key: String },
+}
+
+/// A freshly generated data key, ready to encrypt with, along with its wrapped form to send to consumers.
+struct DataKey {
+    key_id: String,
+    cipher: Aes256Gcm,
+    wrapped: Vec<u8>,
+}
+
+/// Why a data key could not be generated.
+#[derive(Debug, thiserror::Error)]
+enum KeyProviderError {
+    #[error("KMS failed to generate a data key: {0}")]
+    Kms(String),
+    #[error("the static encryption key must be 32 base64 encoded bytes")]
+    InvalidStaticKey,
+}
+
+impl EncryptionSettings {
+    /// Describes why the settings can never produce a data key, such as a static key that is not
+    /// 32 base64 encoded bytes, so the sweeper refuses to start rather than failing every message.
+    pub fn check(&self) -> Result<(), String> {
+        match self {
+            EncryptionSettings::Kms { .. } => Ok(()),
+            EncryptionSettings::Static { key, .. } => static_wrapping_cipher(key).map(|_| ()).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+fn static_wrapping_cipher(key: &str) -> Result<Aes256Gcm, KeyProviderError> {
+    let key = BASE64.decode(key).map_err(|_| KeyProviderError::InvalidStaticKey)?;
+    Aes256Gcm::new_from_slice(&key).map_err(|_| KeyProviderError::InvalidStaticKey)
+}
+
+async fn generate_data_key(kms_client: &KmsClient, settings: &EncryptionSettings) -> Result<DataKey, KeyProviderError> {
+    match settings {
+        EncryptionSettings::Kms { key_id } => {
+            let response = kms_client
+                .generate_data_key()
+                .key_id(key_id)
+                .key_spec(DataKeySpec::Aes256)
+                .send()
+                .await
+                .map_err(|e| KeyProviderError::Kms(DisplayErrorContext(e).to_string()))?;
+            let (Some(plaintext), Some(wrapped)) = (response.plaintext(), response.ciphertext_blob()) else {
+                return Err(KeyProviderError::Kms("the response was missing the data key".to_string()));
+            };
+            Ok(DataKey {
+                key_id: response.key_id().unwrap_or(key_id).to_string(),
+                cipher: Aes256Gcm::new_from_slice(plaintext.as_ref())
+                    .map_err(|_| KeyProviderError::Kms("the data key was not 32 bytes".to_string()))?,
+                wrapped: wrapped.as_ref().to_vec(),
+            })
+        }
+        EncryptionSettings::Static { key_id, key } => {
+            let wrapping_cipher = static_wrapping_cipher(key)?;
+            let plaintext = Aes256Gcm::generate_key(OsRng);
+            Ok(DataKey {
+                key_id: key_id.clone(),
+                cipher: Aes256Gcm::new(&plaintext),
+                wrapped: seal(&wrapping_cipher, &plaintext).concat(),
+            })
+        }
+    }
+}
+
+/// Encrypts the bytes with a new random nonce, returning the nonce and the ciphertext.
+fn seal(cipher: &Aes256Gcm, plaintext: &[u8]) -> [Vec<u8>; 2] {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce, plaintext).expect("AES-GCM can encrypt any message that fits in memory");
+    [nonce.to_vec(), ciphertext]
+}
+
+/// Encrypts every body with AES-256-GCM under a data key generated for this batch.
+///
+/// Each body is replaced with its base64 encoded ciphertext, and `encryption-key-id`,
+/// `encryption-data-key` and `encryption-nonce` headers are added so a consumer can unwrap the
+/// data key with the same provider and decrypt it. The static provider wraps the data key as its
+/// nonce followed by the ciphertext. When no data key can be generated every message is reported
+/// as failed, to be retried on the next sweep, and none are returned.
+#[instrument(skip_all)]
+pub async fn encrypt_bodies<'m>(
+    kms_client: &KmsClient,
+    settings: Option<&EncryptionSettings>,
+    messages: &'m [OutboxMessage],
+    outcome: &mut DispatchOutcome,
+) -> Cow<'m, [OutboxMessage]> {
+    let Some(settings) = settings else {
+        return Cow::Borrowed(messages);
+    };
+
+    let data_key = match generate_data_key(kms_client, settings).await {
+        Ok(data_key) => data_key,
+        Err(e) => {
+            outcome.failed.extend(messages.iter().map(|msg| FailedMessage::retryable(msg.id, e.to_string())));
+            return Cow::Owned(Vec::new());
+        }
+    };
+
+    let wrapped = BASE64.encode(&data_key.wrapped);
+    let encrypted = messages.iter().filter_map(|msg| {
+        let mut headers = match &msg.headers {
+            None | Some(Value::Null) => serde_json::Map::new(),
+            Some(Value::Object(headers)) => headers.clone(),
+            // Headers that are not an object will be dead-lettered when the attributes are built
+            Some(_) => {
+                outcome.failed.push(FailedMessage::permanent(msg.id, "headers must be a JSON object"));
+                return None;
+            }
+        };
+        let [nonce, ciphertext] = seal(&data_key.cipher, msg.body.as_bytes());
+        headers.insert(KEY_ID_ATTRIBUTE.to_string(), json!(data_key.key_id));
+        headers.insert(ENCRYPTED_DATA_KEY_ATTRIBUTE.to_string(), json!(wrapped));
+        headers.insert(NONCE_ATTRIBUTE.to_string(), json!(BASE64.encode(nonce)));
+
+        Some(OutboxMessage {
+            body: BASE64.encode(ciphertext),
+            headers: Some(Value::Object(headers)),
+            ..msg.clone()
+        })
+    }).collect();
+    Cow::Owned(encrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes_gcm::aead::Nonce;
+    use aws_config::{BehaviorVersion, Region};
+
+    fn kms_client() -> KmsClient {
+        KmsClient::from_conf(aws_sdk_kms::Config::builder().behavior_version(BehaviorVersion::latest()).region(Region::new("eu-west-1")).build())
+    }
+
+    fn nonce(bytes: &[u8]) -> Nonce<Aes256Gcm> {
+        <[u8; 12]>::try_from(bytes).unwrap().into()
+    }
+
+    fn header(msg: &OutboxMessage, name: &str) -> Vec<u8> {
+        BASE64.decode(msg.headers.as_ref().unwrap()[name].as_str().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_static_key_round_trip() {
+        // --- ARRANGE ---
+        let master_key = [7u8; 32];
+        let settings = EncryptionSettings::Static { key_id: "local-test".to_string(), key: BASE64.encode(master_key) };
+        let messages = vec![OutboxMessage::for_test(1, ""), OutboxMessage::for_test(2, "")];
+        let mut outcome = DispatchOutcome::default();
+
+        // --- ACT ---
+        let encrypted = encrypt_bodies(&kms_client(), Some(&settings), &messages, &mut outcome).await;
+
+        // --- ASSERT ---
+        assert!(outcome.failed.is_empty(), "Unexpected failures: {:?}", outcome.failed);
+        assert_ne!(header(&encrypted[0], NONCE_ATTRIBUTE), header(&encrypted[1], NONCE_ATTRIBUTE), "Nonces must never be reused");
+
+        for (original, encrypted) in messages.iter().zip(encrypted.iter()) {
+            assert_ne!(encrypted.body, original.body);
+            assert_eq!(encrypted.headers.as_ref().unwrap()[KEY_ID_ATTRIBUTE], "local-test");
+
+            let wrapped = header(encrypted, ENCRYPTED_DATA_KEY_ATTRIBUTE);
+            let (key_nonce, wrapped_key) = wrapped.split_at(12);
+            let data_key = Aes256Gcm::new(&master_key.into()).decrypt(&nonce(key_nonce), wrapped_key).unwrap();
+
+            let body = Aes256Gcm::new_from_slice(&data_key)
+                .unwrap()
+                .decrypt(&nonce(&header(encrypted, NONCE_ATTRIBUTE)), BASE64.decode(&encrypted.body).unwrap().as_slice())
+                .unwrap();
+            assert_eq!(String::from_utf8(body).unwrap(), original.body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_key_provider_errors_are_retried() {
+        let settings = EncryptionSettings::Static { key_id: "local-test".to_string(), key: BASE64.encode([7u8; 16]) };
+        let messages = vec![OutboxMessage::for_test(1, "")];
+        let mut outcome = DispatchOutcome::default();
+
+        let encrypted = encrypt_bodies(&kms_client(), Some(&settings), &messages, &mut outcome).await;
+
+        assert!(encrypted.is_empty());
+        assert_eq!(outcome.failed, vec![FailedMessage::retryable(1, "the static encryption key must be 32 base64 encoded bytes")]);
+    }
+}
@@ -0,0 +1,194 @@
+use aws_sdk_eventbridge::Client as EventBridgeClient;
+use aws_sdk_eventbridge::error::SdkError;
+use aws_sdk_eventbridge::operation::put_events::PutEventsError;
+use aws_sdk_eventbridge::primitives::DateTime;
+use aws_sdk_eventbridge::types::PutEventsRequestEntry;
+use tracing::instrument;
+use crate::config::ChannelSettings;
+use crate::messaging::{size_limited_batches, DispatchOutcome, FailedMessage};
+use crate::models::OutboxMessage;
+
+/// The maximum number of entries PutEvents accepts in a single call.
+pub const MAX_BATCH_ENTRIES: usize = 10;
+
+/// The maximum size of a PutEvents request, and so of any single entry.
+const MAX_BATCH_BYTES: usize = 256 * 1024;
+
+/// EventBridge counts the `Time` field of every entry as 14 bytes.
+const TIME_BYTES: usize = 14;
+
+/// Puts each message on the event bus, with its `message_type` as the `DetailType`.
+///
+/// Messages are batched to stay within the PutEvents entry and size limits. Entries are matched
+/// back to their messages by position, so that only the entries EventBridge accepted are reported
+/// as sent.
+#[instrument(skip(eventbridge_client, settings, messages))]
+pub async fn send_messages_to_eventbridge(
+    eventbridge_client: &EventBridgeClient,
+    event_bus: &str,
+    settings: &ChannelSettings,
+    messages: &[OutboxMessage],
+) -> Result<DispatchOutcome, SdkError<PutEventsError>> {
+    let source = settings.source();
+    let entry_size = |msg: &OutboxMessage| source.len() + msg.message_type.len() + msg.body.len() + event_bus.len() + TIME_BYTES;
+
+    let mut outcome = DispatchOutcome::default();
+    let (oversized, sendable): (Vec<&OutboxMessage>, Vec<&OutboxMessage>) =
+        messages.iter().partition(|msg| entry_size(msg) > MAX_BATCH_BYTES);
+
+    for msg in oversized {
+        outcome.failed.push(FailedMessage::permanent(
+            msg.id,
+            format!("event is {} bytes, EventBridge accepts at most {MAX_BATCH_BYTES}", entry_size(msg)),
+        ));
+    }
+
+    for batch in size_limited_batches(sendable, MAX_BATCH_ENTRIES, MAX_BATCH_BYTES, entry_size) {
+        let entries: Vec<PutEventsRequestEntry> = batch.iter().map(|msg| {
+            PutEventsRequestEntry::builder()
+                .event_bus_name(event_bus)
+                .source(source)
+                .detail_type(&msg.message_type)
+                .detail(&msg.body)
+                .time(DateTime::from_secs(msg.timestamp.timestamp()))
+                .build()
+        }).collect();
+
+        let response = eventbridge_client
+            .put_events()
+            .set_entries(Some(entries))
+            .send()
+            .await?;
+
+        let results = response.entries();
+        for (index, msg) in batch.iter().enumerate() {
+            match results.get(index) {
+                Some(result) if result.error_code().is_none() => outcome.sent.push(msg.id),
+                Some(result) => {
+                    let code = result.error_code().unwrap_or_default();
+                    let reason = format!("{code}: {}", result.error_message().unwrap_or_default());
+                    // A malformed detail will be rejected however many times it is sent.
+                    if code == "MalformedDetail" {
+                        outcome.failed.push(FailedMessage::permanent(msg.id, reason));
+                    } else {
+                        outcome.failed.push(FailedMessage::retryable(msg.id, reason));
+                    }
+                }
+                None => outcome.failed.push(FailedMessage::retryable(msg.id, "message was missing from the batch response")),
+            }
+        }
+    }
+
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpResponse, HttpServer, web};
+    use aws_sdk_eventbridge::config::{BehaviorVersion, Credentials, Region};
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+
+    type Calls = Arc<Mutex<Vec<Vec<String>>>>;
+
+    /// Rejects details mentioning `malformed` or `throttled` with that error, and accepts the rest.
+    async fn put_events(body: web::Bytes, calls: web::Data<Calls>) -> HttpResponse {
+        let request: Value = serde_json::from_slice(&body).unwrap();
+        let details: Vec<String> = request["Entries"].as_array().unwrap().iter()
+            .map(|entry| entry["Detail"].as_str().unwrap().to_string())
+            .collect();
+
+        let entries: Vec<Value> = details.iter().map(|detail| {
+            if detail.contains("malformed") {
+                json!({ "ErrorCode": "MalformedDetail", "ErrorMessage": "Detail is malformed." })
+            } else if detail.contains("throttled") {
+                json!({ "ErrorCode": "ThrottlingException", "ErrorMessage": "Rate exceeded" })
+            } else {
+                json!({ "EventId": "event-1" })
+            }
+        }).collect();
+        let failed_entry_count = entries.iter().filter(|entry| entry.get("ErrorCode").is_some()).count();
+        calls.lock().unwrap().push(details);
+
+        HttpResponse::Ok()
+            .content_type("application/x-amz-json-1.1")
+            .json(json!({ "FailedEntryCount": failed_entry_count, "Entries": entries }))
+    }
+
+    fn start_server() -> (EventBridgeClient, Calls) {
+        let calls: Calls = Arc::default();
+        let data = web::Data::new(calls.clone());
+        let server = HttpServer::new(move || App::new().app_data(data.clone()).default_service(web::post().to(put_events)))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind test server");
+        let endpoint_url = format!("http://{}", server.addrs()[0]);
+        tokio::spawn(server.run());
+
+        let config = aws_sdk_eventbridge::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("eu-west-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .endpoint_url(endpoint_url)
+            .build();
+        (EventBridgeClient::from_conf(config), calls)
+    }
+
+    fn message_with_body(id: i64, body: String) -> OutboxMessage {
+        OutboxMessage { body, ..OutboxMessage::for_test(id, "eventbridge://orders") }
+    }
+
+    #[tokio::test]
+    async fn test_batches_stay_within_the_entry_and_size_limits() {
+        // --- ARRANGE ---
+        let (eventbridge_client, calls) = start_server();
+        let small: Vec<OutboxMessage> = (1..=12).map(|id| OutboxMessage::for_test(id, "eventbridge://orders")).collect();
+        let large: Vec<OutboxMessage> = (13..=15).map(|id| message_with_body(id, format!(r#"{{ "padding": "{}" }}"#, "x".repeat(100 * 1024)))).collect();
+
+        // --- ACT ---
+        let small_outcome = send_messages_to_eventbridge(&eventbridge_client, "orders", &ChannelSettings::default(), &small).await.expect("PutEvents failed");
+        let large_outcome = send_messages_to_eventbridge(&eventbridge_client, "orders", &ChannelSettings::default(), &large).await.expect("PutEvents failed");
+
+        // --- ASSERT ---
+        assert_eq!(small_outcome.sent, (1..=12).collect::<Vec<i64>>());
+        assert_eq!(large_outcome.sent, vec![13, 14, 15]);
+        let batch_sizes: Vec<usize> = calls.lock().unwrap().iter().map(Vec::len).collect();
+        assert_eq!(batch_sizes, vec![10, 2, 2, 1], "Batches should hold at most 10 entries and 256 KiB");
+    }
+
+    #[tokio::test]
+    async fn test_failed_entries_are_matched_to_their_messages() {
+        // --- ARRANGE ---
+        let (eventbridge_client, _calls) = start_server();
+        let messages = vec![
+            OutboxMessage::for_test(1, "eventbridge://orders"),
+            message_with_body(2, r#"{ "state": "throttled" }"#.to_string()),
+            message_with_body(3, r#"{ "state": "malformed" }"#.to_string()),
+            OutboxMessage::for_test(4, "eventbridge://orders"),
+        ];
+
+        // --- ACT ---
+        let outcome = send_messages_to_eventbridge(&eventbridge_client, "orders", &ChannelSettings::default(), &messages).await.expect("PutEvents failed");
+
+        // --- ASSERT ---
+        assert_eq!(outcome.sent, vec![1, 4]);
+        assert_eq!(outcome.failed, vec![
+            FailedMessage::retryable(2, "ThrottlingException: Rate exceeded"),
+            FailedMessage::permanent(3, "MalformedDetail: Detail is malformed."),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_event_is_a_permanent_failure() {
+        let (eventbridge_client, calls) = start_server();
+        let message = message_with_body(1, "x".repeat(MAX_BATCH_BYTES));
+
+        let outcome = send_messages_to_eventbridge(&eventbridge_client, "orders", &ChannelSettings::default(), &[message]).await.expect("PutEvents failed");
+
+        assert!(outcome.sent.is_empty());
+        assert!(outcome.failed[0].permanent);
+        assert!(outcome.failed[0].reason.starts_with("event is "), "{}", outcome.failed[0].reason);
+        assert!(calls.lock().unwrap().is_empty(), "Oversized event should not have been put");
+    }
+}
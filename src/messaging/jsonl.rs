@@ -0,0 +1,92 @@
+use std::io;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::instrument;
+use crate::messaging::DispatchOutcome;
+use crate::models::OutboxMessage;
+
+/// Writes messages as JSON lines to a local file or to stdout.
+///
+/// Writes are serialised so that batches from overlapping sweeps never interleave their lines.
+#[derive(Default)]
+pub struct JsonlSink {
+    write_lock: Mutex<()>,
+}
+
+impl JsonlSink {
+    /// Appends one JSON line per message to the file, creating it if needed.
+    ///
+    /// The file is fsynced before returning, so messages are only reported as sent once they are
+    /// on disk.
+    #[instrument(skip(self, messages))]
+    pub async fn write_messages_to_file(&self, path: &str, messages: &[OutboxMessage]) -> io::Result<DispatchOutcome> {
+        let lines = to_json_lines(messages)?;
+
+        let _guard = self.write_lock.lock().await;
+        let mut file = OpenOptions::new().create(true).append(true).open(path).await?;
+        file.write_all(&lines).await?;
+        file.sync_all().await?;
+
+        Ok(sent(messages))
+    }
+
+    /// Writes one JSON line per message to stdout.
+    #[instrument(skip_all)]
+    pub async fn write_messages_to_stdout(&self, messages: &[OutboxMessage]) -> io::Result<DispatchOutcome> {
+        let lines = to_json_lines(messages)?;
+
+        let _guard = self.write_lock.lock().await;
+        let mut stdout = tokio::io::stdout();
+        stdout.write_all(&lines).await?;
+        stdout.flush().await?;
+
+        Ok(sent(messages))
+    }
+}
+
+fn to_json_lines(messages: &[OutboxMessage]) -> io::Result<Vec<u8>> {
+    let mut lines = Vec::new();
+    for msg in messages {
+        serde_json::to_writer(&mut lines, msg)?;
+        lines.push(b'\n');
+    }
+    Ok(lines)
+}
+
+fn sent(messages: &[OutboxMessage]) -> DispatchOutcome {
+    DispatchOutcome {
+        sent: messages.iter().map(|msg| msg.id).collect(),
+        failed: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_messages_to_file_appends_json_lines() {
+        // --- ARRANGE ---
+        let path = std::env::temp_dir().join(format!("outbox-{}.jsonl", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        let sink = JsonlSink::default();
+
+        // --- ACT ---
+        let first = sink.write_messages_to_file(path, &[OutboxMessage::for_test(1, "file:///tmp/out.jsonl")]).await.unwrap();
+        let second = sink.write_messages_to_file(path, &[OutboxMessage::for_test(2, "file:///tmp/out.jsonl")]).await.unwrap();
+
+        // --- ASSERT ---
+        assert_eq!(first.sent, vec![1]);
+        assert_eq!(second.sent, vec![2]);
+
+        let contents = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let lines: Vec<serde_json::Value> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2, "Each message should be written as one line");
+        assert_eq!(lines[0]["message_id"], "message-1");
+        assert_eq!(lines[1]["id"], 2);
+        assert_eq!(lines[1]["channel_address"], "file:///tmp/out.jsonl");
+        assert_eq!(lines[1]["trace_parent"], "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01");
+    }
+}
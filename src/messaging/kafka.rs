@@ -0,0 +1,142 @@
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use tracing::instrument;
+use crate::config::ChannelSettings;
+use crate::messaging::cloudevents;
+use crate::messaging::{DispatchOutcome, FailedMessage};
+use crate::models::OutboxMessage;
+
+/// Publishes the messages to a Kafka topic, keyed by their partition key.
+///
+/// Every record is enqueued before any delivery report is awaited, so the batch is in flight
+/// together. A message only counts as sent once the broker has acknowledged it.
+#[instrument(skip(producer, settings, messages))]
+pub async fn send_messages_to_kafka(
+    producer: &FutureProducer,
+    topic: &str,
+    settings: &ChannelSettings,
+    messages: &[OutboxMessage],
+) -> DispatchOutcome {
+    let mut outcome = DispatchOutcome::default();
+    let mut deliveries = Vec::with_capacity(messages.len());
+
+    for msg in messages {
+        let record = FutureRecord::to(topic)
+            .key(msg.record_key())
+            .payload(&msg.body)
+            .headers(record_headers(settings, msg));
+
+        match producer.send_result(record) {
+            Ok(delivery) => deliveries.push((msg.id, delivery)),
+            Err((e, _record)) => outcome.failed.push(FailedMessage::retryable(msg.id, e.to_string())),
+        }
+    }
+
+    for (id, delivery) in deliveries {
+        match delivery.await {
+            Ok(Ok(_)) => outcome.sent.push(id),
+            Ok(Err((e, _message))) => outcome.failed.push(FailedMessage::retryable(id, e.to_string())),
+            Err(_cancelled) => outcome.failed.push(FailedMessage::retryable(id, "delivery report was cancelled")),
+        }
+    }
+
+    outcome
+}
+
+fn record_headers(settings: &ChannelSettings, msg: &OutboxMessage) -> OwnedHeaders {
+    let mut headers = OwnedHeaders::new()
+        .insert(Header { key: "message_id", value: Some(&msg.message_id) })
+        .insert(Header { key: "message_type", value: Some(&msg.message_type) });
+
+    if let Some(trace_parent) = &msg.trace_parent {
+        headers = headers.insert(Header { key: "traceparent", value: Some(trace_parent) });
+    }
+
+    let event_attributes = cloudevents::binary_attributes(settings, msg);
+    if !event_attributes.is_empty() {
+        headers = headers.insert(Header { key: "content-type", value: Some("application/json") });
+    }
+    for (name, value) in event_attributes {
+        headers = headers.insert(Header { key: &format!("ce_{name}"), value: Some(&value) });
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::kafka_producer_config;
+    use rdkafka::consumer::{BaseConsumer, Consumer};
+    use rdkafka::message::Headers;
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::{ClientConfig, Message};
+    use std::time::Duration;
+
+    fn test_message(id: i64, partition_key: Option<&str>) -> OutboxMessage {
+        OutboxMessage {
+            partition_key: partition_key.map(str::to_string),
+            ..OutboxMessage::for_test(id, "kafka://test-topic")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_to_mock_cluster_waits_for_delivery() {
+        // --- ARRANGE ---
+        let cluster = MockCluster::new(3).expect("Failed to create mock cluster");
+        cluster.create_topic("test-topic", 1, 3).expect("Failed to create topic");
+
+        let producer: FutureProducer = kafka_producer_config(&cluster.bootstrap_servers(), 5000)
+            .create()
+            .expect("Failed to create producer");
+        let messages = vec![test_message(1, None), test_message(2, Some("customer-42"))];
+
+        // --- ACT ---
+        let outcome = send_messages_to_kafka(&producer, "test-topic", &ChannelSettings::default(), &messages).await;
+
+        // --- ASSERT ---
+        assert_eq!(outcome.sent, vec![1, 2]);
+        assert!(outcome.failed.is_empty(), "Unexpected failures: {:?}", outcome.failed);
+
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", cluster.bootstrap_servers())
+            .set("group.id", "test-group")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .expect("Failed to create consumer");
+        consumer.subscribe(&["test-topic"]).expect("Failed to subscribe");
+
+        let mut received = Vec::new();
+        while received.len() < messages.len() {
+            if let Some(result) = consumer.poll(Duration::from_secs(5)) {
+                let message = result.expect("Failed to consume message");
+                let header = message.headers().expect("Message had no headers").get(1);
+                received.push((message.key().map(|k| k.to_vec()), header.key.to_string()));
+            }
+        }
+
+        assert_eq!(received[0], (Some(b"message-1".to_vec()), "message_type".to_string()));
+        assert_eq!(received[1], (Some(b"customer-42".to_vec()), "message_type".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_send_to_missing_topic_reports_failures() {
+        // --- ARRANGE ---
+        let cluster = MockCluster::new(1).expect("Failed to create mock cluster");
+        cluster
+            .topic_error("missing-topic", rdkafka::types::RDKafkaRespErr::RD_KAFKA_RESP_ERR_TOPIC_AUTHORIZATION_FAILED)
+            .expect("Failed to set topic error");
+
+        let producer: FutureProducer = kafka_producer_config(&cluster.bootstrap_servers(), 1000)
+            .create()
+            .expect("Failed to create producer");
+        let messages = vec![test_message(1, None)];
+
+        // --- ACT ---
+        let outcome = send_messages_to_kafka(&producer, "missing-topic", &ChannelSettings::default(), &messages).await;
+
+        // --- ASSERT ---
+        assert!(outcome.sent.is_empty(), "Message should not have been sent");
+        assert_eq!(outcome.failed.len(), 1);
+        assert_eq!(outcome.failed[0].id, 1);
+    }
+}
@@ -0,0 +1,186 @@
+use std::time::Duration;
+use aws_sdk_kinesis::Client as KinesisClient;
+use aws_sdk_kinesis::error::SdkError;
+use aws_sdk_kinesis::operation::put_records::PutRecordsError;
+use aws_sdk_kinesis::primitives::Blob;
+use aws_sdk_kinesis::types::PutRecordsRequestEntry;
+use tracing::{instrument, warn};
+use crate::messaging::{size_limited_batches, DispatchOutcome, FailedMessage};
+use crate::models::OutboxMessage;
+
+/// The maximum number of records PutRecords accepts in a single call.
+pub const MAX_BATCH_RECORDS: usize = 500;
+
+/// The maximum size of a PutRecords call, counting both data and partition keys.
+const MAX_BATCH_BYTES: usize = 5 * 1024 * 1024;
+
+/// The maximum size of a single record, counting both data and partition key.
+const MAX_RECORD_BYTES: usize = 1024 * 1024;
+
+/// How many times records that Kinesis rejected are put before giving up until the next sweep.
+const MAX_ATTEMPTS: u32 = 3;
+
+fn record_size(msg: &OutboxMessage) -> usize {
+    msg.body.len() + msg.record_key().len()
+}
+
+/// Puts each message on the stream, partitioned by its partition key.
+///
+/// PutRecords can partially fail, so only the records it reports as failed are put again, up to
+/// `MAX_ATTEMPTS` times. The stream can be given by name or by ARN.
+#[instrument(skip(kinesis_client, messages))]
+pub async fn send_messages_to_kinesis(
+    kinesis_client: &KinesisClient,
+    stream: &str,
+    messages: &[OutboxMessage],
+) -> Result<DispatchOutcome, SdkError<PutRecordsError>> {
+    let mut outcome = DispatchOutcome::default();
+    let (oversized, sendable): (Vec<&OutboxMessage>, Vec<&OutboxMessage>) =
+        messages.iter().partition(|msg| record_size(msg) > MAX_RECORD_BYTES);
+
+    for msg in oversized {
+        outcome.failed.push(FailedMessage::permanent(
+            msg.id,
+            format!("record is {} bytes, Kinesis accepts at most {MAX_RECORD_BYTES}", record_size(msg)),
+        ));
+    }
+
+    for batch in size_limited_batches(sendable, MAX_BATCH_RECORDS, MAX_BATCH_BYTES, record_size) {
+        outcome.extend(put_records_with_retry(kinesis_client, stream, batch).await?);
+    }
+
+    Ok(outcome)
+}
+
+async fn put_records_with_retry(
+    kinesis_client: &KinesisClient,
+    stream: &str,
+    mut pending: Vec<&OutboxMessage>,
+) -> Result<DispatchOutcome, SdkError<PutRecordsError>> {
+    let mut outcome = DispatchOutcome::default();
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let records: Vec<PutRecordsRequestEntry> = pending.iter().map(|msg| {
+            PutRecordsRequestEntry::builder()
+                .partition_key(msg.record_key())
+                .data(Blob::new(msg.body.as_bytes()))
+                .build()
+                .unwrap_or_else(|_| panic!("failed to build record for message with id {}", msg.message_id))
+        }).collect();
+
+        let request = kinesis_client.put_records().set_records(Some(records));
+        let request = if stream.starts_with("arn:") { request.stream_arn(stream) } else { request.stream_name(stream) };
+        let response = request.send().await?;
+
+        // Results are returned in the same order as the records were put.
+        let mut failed = Vec::new();
+        for (msg, result) in pending.iter().zip(response.records()) {
+            match result.error_code() {
+                None => outcome.sent.push(msg.id),
+                Some(code) => failed.push((*msg, format!("{code}: {}", result.error_message().unwrap_or_default()))),
+            }
+        }
+
+        if failed.is_empty() {
+            return Ok(outcome);
+        }
+
+        warn!(failed_record_count = response.failed_record_count(), attempt, "Kinesis rejected some records.");
+        if attempt == MAX_ATTEMPTS {
+            outcome.failed.extend(failed.into_iter().map(|(msg, reason)| FailedMessage::retryable(msg.id, reason)));
+            break;
+        }
+
+        pending = failed.into_iter().map(|(msg, _)| msg).collect();
+        tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempt))).await;
+    }
+
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpResponse, HttpServer, web};
+    use aws_sdk_kinesis::config::{BehaviorVersion, Credentials, Region};
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+
+    type Calls = Arc<Mutex<Vec<Vec<String>>>>;
+
+    /// Rejects the second record of the first call, and accepts everything after that.
+    async fn put_records(body: web::Bytes, calls: web::Data<Calls>) -> HttpResponse {
+        let request: Value = serde_json::from_slice(&body).unwrap();
+        let keys: Vec<String> = request["Records"].as_array().unwrap().iter()
+            .map(|record| record["PartitionKey"].as_str().unwrap().to_string())
+            .collect();
+
+        let mut calls = calls.lock().unwrap();
+        let first_call = calls.is_empty();
+        let records: Vec<Value> = keys.iter().enumerate().map(|(index, _)| {
+            if first_call && index == 1 {
+                json!({ "ErrorCode": "ProvisionedThroughputExceededException", "ErrorMessage": "Rate exceeded" })
+            } else {
+                json!({ "SequenceNumber": "1", "ShardId": "shardId-000000000000" })
+            }
+        }).collect();
+        let failed_record_count = if first_call && keys.len() > 1 { 1 } else { 0 };
+        calls.push(keys);
+
+        HttpResponse::Ok()
+            .content_type("application/x-amz-json-1.1")
+            .json(json!({ "FailedRecordCount": failed_record_count, "Records": records }))
+    }
+
+    fn start_server() -> (KinesisClient, Calls) {
+        let calls: Calls = Arc::default();
+        let data = web::Data::new(calls.clone());
+        let server = HttpServer::new(move || App::new().app_data(data.clone()).default_service(web::post().to(put_records)))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind test server");
+        let endpoint_url = format!("http://{}", server.addrs()[0]);
+        tokio::spawn(server.run());
+
+        let config = aws_sdk_kinesis::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("eu-west-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .endpoint_url(endpoint_url)
+            .build();
+        (KinesisClient::from_conf(config), calls)
+    }
+
+    #[tokio::test]
+    async fn test_only_failed_records_are_retried() {
+        // --- ARRANGE ---
+        let (kinesis_client, calls) = start_server();
+        let mut messages: Vec<OutboxMessage> = (1..=3).map(|id| OutboxMessage::for_test(id, "kinesis://analytics")).collect();
+        messages[2].partition_key = Some("customer-42".to_string());
+
+        // --- ACT ---
+        let outcome = send_messages_to_kinesis(&kinesis_client, "analytics", &messages).await.expect("PutRecords failed");
+
+        // --- ASSERT ---
+        assert_eq!(outcome.sent, vec![1, 3, 2]);
+        assert!(outcome.failed.is_empty(), "Unexpected failures: {:?}", outcome.failed);
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 2, "Expected a single retry");
+        assert_eq!(calls[0], vec!["message-1", "message-2", "customer-42"]);
+        assert_eq!(calls[1], vec!["message-2"]);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_record_is_a_permanent_failure() {
+        let (kinesis_client, calls) = start_server();
+        let mut message = OutboxMessage::for_test(1, "kinesis://analytics");
+        message.body = "x".repeat(MAX_RECORD_BYTES);
+
+        let outcome = send_messages_to_kinesis(&kinesis_client, "analytics", &[message]).await.expect("PutRecords failed");
+
+        assert!(outcome.sent.is_empty());
+        assert!(outcome.failed[0].permanent);
+        assert!(calls.lock().unwrap().is_empty(), "Oversized record should not have been put");
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use crate::messaging::{DispatchOutcome, FailedMessage};
+use crate::models::OutboxMessage;
+
+/// The error returned for a call the transport was told to fail.
+#[derive(Debug, thiserror::Error)]
+#[error("the in-memory transport was told to fail this call")]
+pub struct CallFailed;
+
+/// Keeps every batch sent to a `memory://` channel in memory instead of sending it anywhere.
+///
+/// Tests can tell it to fail whole calls, or to reject individual messages by `message_id`, so
+/// the sweeper's handling of each kind of failure can be checked without any real broker.
+#[derive(Default)]
+pub struct MemoryTransport {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    batches: Vec<(String, Vec<String>)>,
+    failing_calls: usize,
+    failing_messages: HashMap<String, FailedMessage>,
+}
+
+impl MemoryTransport {
+    /// Records the batch against the channel, rejecting any messages it has been told to fail.
+    pub fn send_messages(&self, channel: &str, messages: &[OutboxMessage]) -> Result<DispatchOutcome, CallFailed> {
+        let mut state = self.state.lock().expect("memory transport lock was poisoned");
+        if state.failing_calls > 0 {
+            state.failing_calls -= 1;
+            return Err(CallFailed);
+        }
+
+        let mut outcome = DispatchOutcome::default();
+        let mut accepted = Vec::new();
+        for msg in messages {
+            match state.failing_messages.get(&msg.message_id) {
+                Some(failure) => outcome.failed.push(FailedMessage { id: msg.id, ..failure.clone() }),
+                None => {
+                    outcome.sent.push(msg.id);
+                    accepted.push(msg.message_id.clone());
+                }
+            }
+        }
+        state.batches.push((channel.to_string(), accepted));
+
+        Ok(outcome)
+    }
+
+    /// Returns the `message_id`s accepted by each call, along with the channel they were sent to.
+    pub fn batches(&self) -> Vec<(String, Vec<String>)> {
+        self.state.lock().unwrap().batches.clone()
+    }
+
+    /// Fails the next `calls` calls outright, without accepting any of their messages.
+    pub fn fail_next_calls(&self, calls: usize) {
+        self.state.lock().unwrap().failing_calls = calls;
+    }
+
+    /// Rejects the message every time it is sent.
+    pub fn fail_message(&self, message_id: &str, permanent: bool) {
+        let reason = "the in-memory transport was told to fail this message";
+        let failure = if permanent { FailedMessage::permanent(0, reason) } else { FailedMessage::retryable(0, reason) };
+        self.state.lock().unwrap().failing_messages.insert(message_id.to_string(), failure);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failures_are_applied() {
+        let transport = MemoryTransport::default();
+        let messages = vec![OutboxMessage::for_test(1, ""), OutboxMessage::for_test(2, "")];
+        transport.fail_message("message-2", true);
+        transport.fail_next_calls(1);
+
+        assert!(transport.send_messages("orders", &messages).is_err(), "The first call should fail");
+
+        let outcome = transport.send_messages("orders", &messages).unwrap();
+        assert_eq!(outcome.sent, vec![1]);
+        assert_eq!(outcome.failed, vec![FailedMessage::permanent(2, "the in-memory transport was told to fail this message")]);
+        assert_eq!(transport.batches(), vec![("orders".to_string(), vec!["message-1".to_string()])]);
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tokio::sync::Mutex;
+use tracing::instrument;
+use crate::clients::db_pool_options;
+use crate::config::ChannelSettings;
+use crate::messaging::DispatchOutcome;
+use crate::models::OutboxMessage;
+
+/// The inbox table used when a channel does not configure one.
+const DEFAULT_TABLE: &str = "inbox";
+
+/// Delivers messages straight into an `inbox` table in another Postgres database.
+///
+/// A connection pool is created the first time a database is used and reused by every later
+/// sweep.
+#[derive(Default)]
+pub struct PostgresInboxTransport {
+    pools: Mutex<HashMap<String, PgPool>>,
+}
+
+impl PostgresInboxTransport {
+    async fn pool(&self, database_url: &str) -> Result<PgPool, sqlx::Error> {
+        let mut pools = self.pools.lock().await;
+        if let Some(pool) = pools.get(database_url) {
+            return Ok(pool.clone());
+        }
+
+        let pool = db_pool_options().connect_lazy(database_url)?;
+        pools.insert(database_url.to_string(), pool.clone());
+        Ok(pool)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn send_messages(
+        &self,
+        database_url: &str,
+        settings: &ChannelSettings,
+        messages: &[OutboxMessage],
+    ) -> Result<DispatchOutcome, sqlx::Error> {
+        let pool = self.pool(database_url).await?;
+        insert_into_inbox(&pool, settings.table.as_deref().unwrap_or(DEFAULT_TABLE), messages).await
+    }
+}
+
+/// Inserts the messages into the inbox table as a single statement.
+///
+/// Messages the inbox already holds are skipped with `ON CONFLICT (message_id) DO NOTHING`, so a
+/// batch that is re-sent after failing to be marked as dispatched is only delivered once.
+pub async fn insert_into_inbox(
+    pool: &PgPool,
+    table: &str,
+    messages: &[OutboxMessage],
+) -> Result<DispatchOutcome, sqlx::Error> {
+    if !is_valid_table_name(table) {
+        return Err(sqlx::Error::Configuration(format!("invalid inbox table name {table:?}").into()));
+    }
+
+    let message_ids: Vec<&str> = messages.iter().map(|m| m.message_id.as_str()).collect();
+    let message_types: Vec<&str> = messages.iter().map(|m| m.message_type.as_str()).collect();
+    let timestamps: Vec<DateTime<Utc>> = messages.iter().map(|m| m.timestamp).collect();
+    let bodies: Vec<&str> = messages.iter().map(|m| m.body.as_str()).collect();
+    let trace_parents: Vec<Option<&str>> = messages.iter().map(|m| m.trace_parent.as_deref()).collect();
+
+    sqlx::query(&format!(
+        r#"
+        INSERT INTO {table} (message_id, message_type, "timestamp", body, trace_parent)
+        SELECT * FROM UNNEST($1::VARCHAR[], $2::VARCHAR[], $3::TIMESTAMPTZ[], $4::TEXT[], $5::VARCHAR[])
+        ON CONFLICT (message_id) DO NOTHING
+        "#,
+    ))
+        .bind(message_ids)
+        .bind(message_types)
+        .bind(timestamps)
+        .bind(bodies)
+        .bind(trace_parents)
+        .execute(pool)
+        .await?;
+
+    Ok(DispatchOutcome {
+        sent: messages.iter().map(|m| m.id).collect(),
+        failed: Vec::new(),
+    })
+}
+
+/// The table name is put straight into the SQL, so it may only be a plain, optionally schema
+/// qualified, identifier.
+fn is_valid_table_name(table: &str) -> bool {
+    let parts: Vec<&str> = table.split('.').collect();
+    parts.len() <= 2
+        && parts.iter().all(|part| {
+            part.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{Executor, Row};
+
+    #[sqlx::test(migrations = false)]
+    async fn test_insert_into_inbox_is_idempotent(pool: PgPool) {
+        // --- ARRANGE ---
+        let inbox_sql = include_str!("../../inbox.sql");
+        pool.execute(inbox_sql).await.expect("Failed to create inbox");
+        let messages = vec![OutboxMessage::for_test(1, ""), OutboxMessage::for_test(2, "")];
+
+        // --- ACT ---
+        let first = insert_into_inbox(&pool, "inbox", &messages).await.expect("First insert failed");
+        let second = insert_into_inbox(&pool, "inbox", &messages).await.expect("Second insert failed");
+
+        // --- ASSERT ---
+        assert_eq!(first.sent, vec![1, 2]);
+        assert_eq!(second.sent, vec![1, 2], "Re-sent messages should still count as delivered");
+
+        let rows = sqlx::query("SELECT message_id FROM inbox ORDER BY message_id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let message_ids: Vec<String> = rows.iter().map(|row| row.get("message_id")).collect();
+        assert_eq!(message_ids, vec!["message-1", "message-2"], "Messages were not inserted exactly once");
+    }
+
+    #[test]
+    fn test_is_valid_table_name() {
+        assert!(is_valid_table_name("inbox"));
+        assert!(is_valid_table_name("core.inbox"));
+        assert!(!is_valid_table_name("inbox; DROP TABLE inbox"));
+        assert!(!is_valid_table_name("a.b.c"));
+        assert!(!is_valid_table_name(""));
+    }
+}
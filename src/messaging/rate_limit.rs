@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::config::ChannelSettings;
+
+/// A bucket that refills at `rate` tokens a second and holds at most `capacity` of them.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket, which can hold a second's worth of tokens and never less than one.
+    fn new(rate: f64, now: Instant) -> Self {
+        let capacity = rate.max(1.0);
+        Self { rate, capacity, tokens: capacity, updated: now }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// How long until the bucket holds a whole token.
+    fn wait(&self) -> Duration {
+        Duration::from_secs_f64(((1.0 - self.tokens) / self.rate).max(0.0))
+    }
+}
+
+/// The buckets of a single channel address.
+#[derive(Debug, Default)]
+struct Buckets {
+    requests: Option<TokenBucket>,
+    messages: Option<TokenBucket>,
+}
+
+/// Limits how many transport calls, and how many messages, each channel address is sent a second.
+///
+/// Each limited channel has a token bucket for requests and one for messages, created the first
+/// time the channel is sent to. Channels without `requests_per_second` or `messages_per_second`
+/// settings are never limited.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Buckets>>,
+}
+
+impl RateLimiter {
+    /// Takes up to `messages` message tokens for the channel, and a request token for every call
+    /// needed to send them when the transport sends `batch_size` messages a call.
+    ///
+    /// Returns how many of the messages may be sent now, which is at least one, or how long to
+    /// wait before anything can be sent.
+    pub fn acquire(&self, channel_address: &str, settings: &ChannelSettings, messages: usize, batch_size: usize) -> Result<usize, Duration> {
+        self.acquire_at(channel_address, settings, messages, batch_size, Instant::now())
+    }
+
+    fn acquire_at(&self, channel_address: &str, settings: &ChannelSettings, messages: usize, batch_size: usize, now: Instant) -> Result<usize, Duration> {
+        if settings.requests_per_second.is_none() && settings.messages_per_second.is_none() {
+            return Ok(messages);
+        }
+
+        let mut buckets = self.buckets.lock().expect("rate limiter lock was poisoned");
+        let buckets = buckets.entry(channel_address.to_string()).or_insert_with(|| Buckets {
+            requests: settings.requests_per_second.map(|rate| TokenBucket::new(rate, now)),
+            messages: settings.messages_per_second.map(|rate| TokenBucket::new(rate, now)),
+        });
+
+        let mut allowed = messages;
+        if let Some(requests) = &mut buckets.requests {
+            requests.refill(now);
+            if requests.tokens < 1.0 {
+                return Err(requests.wait());
+            }
+            allowed = allowed.min((requests.tokens as usize).saturating_mul(batch_size));
+        }
+        if let Some(bucket) = &mut buckets.messages {
+            bucket.refill(now);
+            if bucket.tokens < 1.0 {
+                return Err(bucket.wait());
+            }
+            allowed = allowed.min(bucket.tokens as usize);
+            bucket.tokens -= allowed as f64;
+        }
+        if let Some(requests) = &mut buckets.requests {
+            requests.tokens -= allowed.div_ceil(batch_size).max(1) as f64;
+        }
+        Ok(allowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_are_limited() {
+        let limiter = RateLimiter::default();
+        let settings = ChannelSettings { requests_per_second: Some(2.0), ..Default::default() };
+        let start = Instant::now();
+
+        assert_eq!(limiter.acquire_at("memory://hook", &settings, 5, usize::MAX, start), Ok(5));
+        assert_eq!(limiter.acquire_at("memory://hook", &settings, 5, usize::MAX, start), Ok(5));
+        assert_eq!(limiter.acquire_at("memory://hook", &settings, 5, usize::MAX, start), Err(Duration::from_millis(500)));
+        assert_eq!(limiter.acquire_at("memory://hook", &settings, 5, usize::MAX, start + Duration::from_millis(500)), Ok(5));
+        assert_eq!(limiter.acquire_at("memory://other", &settings, 5, usize::MAX, start), Ok(5), "Each channel should have its own buckets");
+    }
+
+    #[test]
+    fn test_every_call_of_a_batch_takes_a_request_token() {
+        let limiter = RateLimiter::default();
+        let settings = ChannelSettings { requests_per_second: Some(3.0), ..Default::default() };
+        let start = Instant::now();
+
+        assert_eq!(limiter.acquire_at("https://example.com/hook", &settings, 5, 1, start), Ok(3), "A call per message should trim the batch to the requests available");
+        assert_eq!(limiter.acquire_at("https://example.com/hook", &settings, 5, 1, start), Err(Duration::from_secs_f64(1.0 / 3.0)));
+
+        assert_eq!(limiter.acquire_at("SNS::topic", &settings, 25, 10, start), Ok(25), "25 messages should need 3 calls of 10");
+        assert_eq!(limiter.acquire_at("SNS::topic", &settings, 1, 10, start), Err(Duration::from_secs_f64(1.0 / 3.0)));
+    }
+
+    #[test]
+    fn test_batches_are_trimmed_to_the_messages_available() {
+        let limiter = RateLimiter::default();
+        let settings = ChannelSettings { messages_per_second: Some(4.0), ..Default::default() };
+        let start = Instant::now();
+
+        assert_eq!(limiter.acquire_at("memory://sms", &settings, 10, usize::MAX, start), Ok(4));
+        assert_eq!(limiter.acquire_at("memory://sms", &settings, 10, usize::MAX, start), Err(Duration::from_millis(250)));
+        assert_eq!(limiter.acquire_at("memory://sms", &settings, 10, usize::MAX, start + Duration::from_secs(10)), Ok(4), "Tokens should not build up beyond a second's worth");
+    }
+
+    #[test]
+    fn test_slow_rates_still_allow_a_message() {
+        let limiter = RateLimiter::default();
+        let settings = ChannelSettings { messages_per_second: Some(0.5), ..Default::default() };
+        let start = Instant::now();
+
+        assert_eq!(limiter.acquire_at("memory://slow", &settings, 3, usize::MAX, start), Ok(1));
+        assert_eq!(limiter.acquire_at("memory://slow", &settings, 3, usize::MAX, start), Err(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_unlimited_channels_are_not_tracked() {
+        let limiter = RateLimiter::default();
+
+        assert_eq!(limiter.acquire("memory://free", &ChannelSettings::default(), 10, 1), Ok(10));
+        assert!(limiter.buckets.lock().unwrap().is_empty());
+    }
+}
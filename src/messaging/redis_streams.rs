@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use redis::aio::MultiplexedConnection;
+use redis::streams::StreamMaxlen;
+use tokio::sync::Mutex;
+use tracing::{info, instrument};
+use crate::config::ChannelSettings;
+use crate::messaging::{DispatchOutcome, FailedMessage};
+use crate::models::OutboxMessage;
+
+/// Appends messages to Redis Streams, keeping one connection open per Redis server.
+///
+/// Connections are opened the first time a server is used and reused by every later sweep. A
+/// connection that errors is dropped and reopened on the next dispatch.
+#[derive(Default)]
+pub struct RedisTransport {
+    connections: Mutex<HashMap<String, MultiplexedConnection>>,
+}
+
+/// Splits a `redis://host:port[/db]/stream` address into the server URL and the stream name.
+pub fn split_stream_address(address: &str) -> Option<(&str, &str)> {
+    let (scheme, rest) = address.split_once("://")?;
+    let (server, stream) = rest.rsplit_once('/')?;
+    if server.is_empty() || stream.is_empty() {
+        return None;
+    }
+    Some((&address[..scheme.len() + 3 + server.len()], stream))
+}
+
+impl RedisTransport {
+    async fn connection(&self, server_url: &str) -> redis::RedisResult<MultiplexedConnection> {
+        let mut connections = self.connections.lock().await;
+        if let Some(connection) = connections.get(server_url) {
+            return Ok(connection.clone());
+        }
+
+        info!("Opening Redis connection.");
+        let connection = redis::Client::open(server_url)?.get_multiplexed_async_connection().await?;
+        connections.insert(server_url.to_string(), connection.clone());
+        Ok(connection)
+    }
+
+    /// Adds every message to the stream with a single pipelined `XADD` transaction.
+    ///
+    /// The pipeline is sent as `MULTI`/`EXEC`, so either every message is added or none are, and
+    /// the messages are only reported as sent once Redis has returned an entry id for them. When
+    /// the channel sets `max_len` the stream is trimmed with `MAXLEN ~`.
+    #[instrument(skip_all, fields(stream))]
+    pub async fn send_messages(
+        &self,
+        address: &str,
+        settings: &ChannelSettings,
+        messages: &[OutboxMessage],
+    ) -> redis::RedisResult<DispatchOutcome> {
+        let (server_url, stream) = split_stream_address(address).ok_or_else(|| {
+            redis::RedisError::from((redis::ErrorKind::InvalidClientConfig, "channel address must be redis://host/stream"))
+        })?;
+        tracing::Span::current().record("stream", stream);
+
+        let pipe = xadd_pipeline(stream, settings, messages);
+        let mut connection = self.connection(server_url).await?;
+        match pipe.query_async::<Vec<String>>(&mut connection).await {
+            Ok(entry_ids) => Ok(outcome(messages, &entry_ids)),
+            Err(e) => {
+                self.connections.lock().await.remove(server_url);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Builds the `MULTI`/`EXEC` pipeline that adds each message to the stream, trimming it with
+/// `MAXLEN ~` when the channel sets `max_len`.
+fn xadd_pipeline(stream: &str, settings: &ChannelSettings, messages: &[OutboxMessage]) -> redis::Pipeline {
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    for msg in messages {
+        let fields = stream_fields(msg);
+        match settings.max_len {
+            Some(max_len) => pipe.xadd_maxlen(stream, StreamMaxlen::Approx(max_len), "*", &fields),
+            None => pipe.xadd(stream, "*", &fields),
+        };
+    }
+    pipe
+}
+
+/// Matches the entry ids `EXEC` returned back onto the messages, in the order they were added.
+fn outcome(messages: &[OutboxMessage], entry_ids: &[String]) -> DispatchOutcome {
+    let mut outcome = DispatchOutcome::default();
+    for (index, msg) in messages.iter().enumerate() {
+        match entry_ids.get(index) {
+            Some(_) => outcome.sent.push(msg.id),
+            None => outcome.failed.push(FailedMessage::retryable(msg.id, "Redis did not return an entry id for the message")),
+        }
+    }
+    outcome
+}
+
+fn stream_fields(msg: &OutboxMessage) -> Vec<(&str, &str)> {
+    let mut fields = vec![
+        ("body", msg.body.as_str()),
+        ("message_id", msg.message_id.as_str()),
+        ("message_type", msg.message_type.as_str()),
+    ];
+    if let Some(trace_parent) = &msg.trace_parent {
+        fields.push(("traceparent", trace_parent.as_str()));
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_stream_address() {
+        let cases = vec![
+            ("redis://localhost/events", Some(("redis://localhost", "events"))),
+            ("redis://:secret@localhost:6379/2/events", Some(("redis://:secret@localhost:6379/2", "events"))),
+            ("rediss://cache.example.com:6380/events", Some(("rediss://cache.example.com:6380", "events"))),
+            ("redis://localhost", None),
+            ("redis://localhost/", None),
+        ];
+
+        for (address, expected) in cases {
+            assert_eq!(split_stream_address(address), expected, "{address}");
+        }
+    }
+
+    fn commands(pipe: &redis::Pipeline) -> Vec<Vec<String>> {
+        pipe.cmd_iter().map(|cmd| cmd.args_iter().map(|arg| match arg {
+            redis::Arg::Simple(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+            _ => "<cursor>".to_string(),
+        }).collect()).collect()
+    }
+
+    #[test]
+    fn test_pipeline_adds_every_message() {
+        let messages = vec![OutboxMessage { trace_parent: None, ..OutboxMessage::for_test(1, "") }, OutboxMessage::for_test(2, "")];
+
+        let pipe = xadd_pipeline("events", &ChannelSettings::default(), &messages);
+
+        assert!(pipe.is_transaction(), "Messages should be added in a MULTI/EXEC transaction");
+        let commands = commands(&pipe);
+        assert_eq!(commands[0], vec!["XADD", "events", "*", "body", r#"{ "id": 1 }"#, "message_id", "message-1", "message_type", "test.topic"]);
+        assert_eq!(commands[1][..3], ["XADD", "events", "*"]);
+        assert_eq!(commands[1].last().map(String::as_str), Some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"));
+    }
+
+    #[test]
+    fn test_pipeline_trims_the_stream() {
+        let settings = ChannelSettings { max_len: Some(1000), ..Default::default() };
+
+        let pipe = xadd_pipeline("events", &settings, &[OutboxMessage::for_test(1, "")]);
+
+        assert_eq!(commands(&pipe)[0][..6], ["XADD", "events", "MAXLEN", "~", "1000", "*"]);
+    }
+
+    #[test]
+    fn test_messages_without_an_entry_id_are_retried() {
+        let messages = vec![OutboxMessage::for_test(1, ""), OutboxMessage::for_test(2, "")];
+
+        let outcome = outcome(&messages, &["1700000000000-0".to_string()]);
+
+        assert_eq!(outcome.sent, vec![1]);
+        assert_eq!(outcome.failed, vec![FailedMessage::retryable(2, "Redis did not return an entry id for the message")]);
+    }
+}
@@ -0,0 +1,170 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use jsonschema::Validator;
+use serde_json::Value;
+use tracing::info;
+use crate::messaging::{DispatchOutcome, FailedMessage};
+use crate::models::OutboxMessage;
+
+/// At most this many validation errors are kept as the dead-letter reason of a message.
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// Why the schema directory could not be loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum SchemaError {
+    #[error("failed to read schema {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("schema {0} is not valid JSON: {1}")]
+    Parse(PathBuf, serde_json::Error),
+    #[error("the schema for {0} is not a valid JSON Schema: {1}")]
+    Invalid(String, String),
+}
+
+/// The JSON Schema each message type's bodies must match, loaded from the directory at `SCHEMA_DIR`.
+///
+/// Each `{message_type}.json` file in the directory is the schema for that message type, e.g.
+/// `order.created.json`. Message types without a schema are not validated.
+#[derive(Default)]
+pub struct Schemas {
+    validators: HashMap<String, Validator>,
+}
+
+impl Schemas {
+    /// Reads and compiles every schema in the directory, so a broken schema stops the sweeper from
+    /// starting rather than dead-lettering every message.
+    pub fn load(dir: &Path) -> Result<Self, SchemaError> {
+        let mut schemas = Self::default();
+        let entries = std::fs::read_dir(dir).map_err(|e| SchemaError::Io(dir.to_path_buf(), e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| SchemaError::Io(dir.to_path_buf(), e))?.path();
+            let (Some(message_type), Some("json")) = (path.file_stem().and_then(|s| s.to_str()), path.extension().and_then(|e| e.to_str())) else {
+                continue;
+            };
+            let contents = std::fs::read_to_string(&path).map_err(|e| SchemaError::Io(path.clone(), e))?;
+            let schema = serde_json::from_str(&contents).map_err(|e| SchemaError::Parse(path.clone(), e))?;
+            schemas.add(message_type, &schema)?;
+        }
+        info!(schemas = schemas.validators.len(), dir = %dir.display(), "JSON schemas loaded.");
+        Ok(schemas)
+    }
+
+    /// Compiles a schema and validates messages of the type against it.
+    pub fn add(&mut self, message_type: &str, schema: &Value) -> Result<(), SchemaError> {
+        let validator = jsonschema::validator_for(schema).map_err(|e| SchemaError::Invalid(message_type.to_string(), e.to_string()))?;
+        self.validators.insert(message_type.to_string(), validator);
+        Ok(())
+    }
+
+    /// Checks each body against the schema for its message type, if there is one.
+    ///
+    /// A message whose body is not valid JSON or does not match its schema is reported as a
+    /// permanent failure, with the validation errors as the reason, and left out of the result.
+    pub fn validate<'m>(&self, messages: &'m [OutboxMessage], outcome: &mut DispatchOutcome) -> Cow<'m, [OutboxMessage]> {
+        if !messages.iter().any(|msg| self.validators.contains_key(&msg.message_type)) {
+            return Cow::Borrowed(messages);
+        }
+
+        let (valid, invalid): (Vec<_>, Vec<_>) = messages.iter().partition(|msg| {
+            let Some(validator) = self.validators.get(&msg.message_type) else {
+                return true;
+            };
+            match errors(validator, msg) {
+                None => true,
+                Some(reason) => {
+                    outcome.failed.push(FailedMessage::permanent(msg.id, reason));
+                    false
+                }
+            }
+        });
+        if invalid.is_empty() {
+            return Cow::Borrowed(messages);
+        }
+        Cow::Owned(valid.into_iter().cloned().collect())
+    }
+}
+
+/// Describes why the body does not match the schema, or returns `None` when it does.
+fn errors(validator: &Validator, msg: &OutboxMessage) -> Option<String> {
+    let body: Value = match serde_json::from_str(&msg.body) {
+        Ok(body) => body,
+        Err(e) => return Some(format!("body is not valid JSON: {e}")),
+    };
+
+    let errors: Vec<String> = validator.iter_errors(&body).take(MAX_REPORTED_ERRORS).map(|e| {
+        let path = e.instance_path().to_string();
+        if path.is_empty() { e.to_string() } else { format!("{path}: {e}") }
+    }).collect();
+    if errors.is_empty() {
+        return None;
+    }
+    Some(format!("body does not match the {} schema: {}", msg.message_type, errors.join("; ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schemas() -> Schemas {
+        let mut schemas = Schemas::default();
+        schemas.add("test.topic", &json!({
+            "type": "object",
+            "properties": { "id": { "type": "integer" }, "region": { "enum": ["eu", "us"] } },
+            "required": ["id"],
+        })).unwrap();
+        schemas
+    }
+
+    fn message_with_body(id: i64, body: &str) -> OutboxMessage {
+        OutboxMessage { body: body.to_string(), ..OutboxMessage::for_test(id, "") }
+    }
+
+    #[test]
+    fn test_invalid_bodies_are_permanent_failures() {
+        let messages = vec![
+            message_with_body(1, r#"{ "id": 1, "region": "eu" }"#),
+            message_with_body(2, r#"{ "id": "two", "region": "apac" }"#),
+            message_with_body(3, r#"{ "foo"": "bar" }"#),
+            OutboxMessage { message_type: "other.topic".to_string(), ..message_with_body(4, "not json") },
+        ];
+        let mut outcome = DispatchOutcome::default();
+
+        let valid = schemas().validate(&messages, &mut outcome);
+
+        let valid_ids: Vec<i64> = valid.iter().map(|m| m.id).collect();
+        assert_eq!(valid_ids, vec![1, 4], "Only valid bodies and types without a schema should be sent");
+        assert_eq!(outcome.failed.len(), 2);
+        assert!(outcome.failed.iter().all(|f| f.permanent));
+        assert!(outcome.failed[0].reason.starts_with("body does not match the test.topic schema: "), "{}", outcome.failed[0].reason);
+        assert!(outcome.failed[0].reason.contains("/id: "), "{}", outcome.failed[0].reason);
+        assert!(outcome.failed[0].reason.contains("/region: "), "{}", outcome.failed[0].reason);
+        assert!(outcome.failed[1].reason.starts_with("body is not valid JSON: "), "{}", outcome.failed[1].reason);
+    }
+
+    #[test]
+    fn test_nothing_is_copied_when_every_body_is_valid() {
+        let messages = vec![message_with_body(1, r#"{ "id": 1 }"#)];
+        let mut outcome = DispatchOutcome::default();
+
+        assert!(matches!(schemas().validate(&messages, &mut outcome), Cow::Borrowed(_)));
+        assert!(matches!(Schemas::default().validate(&[message_with_body(2, "not json")], &mut outcome), Cow::Borrowed(_)));
+        assert!(outcome.failed.is_empty());
+    }
+
+    #[test]
+    fn test_schemas_are_loaded_by_file_name() {
+        let dir = std::env::temp_dir().join(format!("outbox-schemas-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("order.created.json"), r#"{ "type": "object" }"#).unwrap();
+        std::fs::write(dir.join("README.md"), "Not a schema").unwrap();
+
+        let schemas = Schemas::load(&dir).unwrap();
+        std::fs::write(dir.join("order.updated.json"), r#"{ "type": 12 }"#).unwrap();
+        let invalid = Schemas::load(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(schemas.validators.keys().collect::<Vec<_>>(), vec!["order.created"]);
+        assert!(matches!(invalid, Err(SchemaError::Invalid(message_type, _)) if message_type == "order.updated"));
+    }
+}
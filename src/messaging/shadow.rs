@@ -0,0 +1,52 @@
+use crate::config::ChannelSettings;
+use crate::models::OutboxMessage;
+
+/// The most shadow sends that may be in flight at once. Samples taken while they are all in use
+/// are dropped, so a slow shadow destination can not build up work.
+pub const MAX_IN_FLIGHT: usize = 16;
+
+/// The messages of a batch that are copied to the channel's shadow destination.
+///
+/// Whether a message is in the sample depends only on its `message_id`, so a message that is
+/// retried is copied again, and the same messages are picked by every sweeper.
+pub fn sample<'m>(settings: &ChannelSettings, messages: &'m [OutboxMessage]) -> Vec<&'m OutboxMessage> {
+    let percent = settings.shadow_percent.unwrap_or(100.0);
+    messages.iter().filter(|msg| in_sample(&msg.message_id, percent)).collect()
+}
+
+/// Buckets the message id into one of 10,000 buckets with FNV-1a, which is stable across releases.
+fn in_sample(message_id: &str, percent: f64) -> bool {
+    let hash = message_id.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    ((hash % 10_000) as f64) < percent * 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(count: i64) -> Vec<OutboxMessage> {
+        (1..=count).map(|id| OutboxMessage::for_test(id, "")).collect()
+    }
+
+    #[test]
+    fn test_sample_follows_the_percentage() {
+        let messages = messages(1000);
+        let sampled = |shadow_percent| sample(&ChannelSettings { shadow_percent: Some(shadow_percent), ..Default::default() }, &messages).len();
+
+        assert_eq!(sampled(0.0), 0);
+        assert_eq!(sampled(100.0), 1000);
+        assert!((400..=600).contains(&sampled(50.0)), "About half should be sampled, not {}", sampled(50.0));
+        assert_eq!(sample(&ChannelSettings::default(), &messages).len(), 1000, "Every message should be copied by default");
+    }
+
+    #[test]
+    fn test_sample_is_stable() {
+        let messages = messages(100);
+        let settings = ChannelSettings { shadow_percent: Some(25.0), ..Default::default() };
+
+        let first: Vec<i64> = sample(&settings, &messages).iter().map(|m| m.id).collect();
+        let second: Vec<i64> = sample(&settings, &messages).iter().map(|m| m.id).collect();
+
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,192 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde_json::Value;
+use crate::messaging::{DispatchOutcome, FailedMessage};
+use crate::models::OutboxMessage;
+
+/// The most operations a script may run for a single message.
+const MAX_OPERATIONS: u64 = 100_000;
+/// The deepest function calls may be nested.
+const MAX_CALL_LEVELS: usize = 32;
+/// The deepest expressions may be nested, at the top level and inside functions.
+const MAX_EXPR_DEPTH: usize = 64;
+/// The largest string, array or object map a script may build.
+const MAX_STRING_BYTES: usize = 1024 * 1024;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+
+/// Why the transform scripts could not be loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum TransformError {
+    #[error("failed to read transform script {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("failed to compile the transform script for {0}: {1}")]
+    Compile(String, rhai::ParseError),
+}
+
+/// Rhai scripts that reshape messages of a type before they are sent.
+///
+/// Each script runs once per message, with the parsed JSON `body` and the `headers` object in scope,
+/// along with the `message_id` and `message_type` as constants. Whatever `body` and `headers` hold
+/// when the script ends is sent, e.g.
+///
+/// ```rhai
+/// body.customer_id = body.customerId;
+/// body.remove("customerId");
+/// headers.schema_version = "2";
+/// ```
+///
+/// A body that is not valid JSON is given to the script as a string, and a `body` that is a string
+/// when the script ends is sent as it is.
+pub struct Transforms {
+    engine: Engine,
+    scripts: HashMap<String, AST>,
+}
+
+impl Default for Transforms {
+    fn default() -> Self {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH)
+            .set_max_string_size(MAX_STRING_BYTES)
+            .set_max_array_size(MAX_COLLECTION_SIZE)
+            .set_max_map_size(MAX_COLLECTION_SIZE);
+        Self { engine, scripts: HashMap::new() }
+    }
+}
+
+impl Transforms {
+    /// Reads and compiles the script for each message type, so a broken script stops the sweeper
+    /// from starting rather than failing every message.
+    pub fn load(scripts: &HashMap<String, PathBuf>) -> Result<Self, TransformError> {
+        let mut transforms = Self::default();
+        for (message_type, path) in scripts {
+            let source = std::fs::read_to_string(path).map_err(|e| TransformError::Io(path.clone(), e))?;
+            transforms.add(message_type, &source)?;
+        }
+        Ok(transforms)
+    }
+
+    /// Compiles a script and uses it for messages of the type.
+    pub fn add(&mut self, message_type: &str, source: &str) -> Result<(), TransformError> {
+        let ast = self.engine.compile(source).map_err(|e| TransformError::Compile(message_type.to_string(), e))?;
+        self.scripts.insert(message_type.to_string(), ast);
+        Ok(())
+    }
+
+    /// Runs each message through the script for its type, if there is one.
+    ///
+    /// A message whose script fails or runs past the execution limits is reported as a permanent
+    /// failure and left out of the result.
+    pub fn apply<'m>(&self, messages: &'m [OutboxMessage], outcome: &mut DispatchOutcome) -> Cow<'m, [OutboxMessage]> {
+        if !messages.iter().any(|msg| self.scripts.contains_key(&msg.message_type)) {
+            return Cow::Borrowed(messages);
+        }
+
+        Cow::Owned(messages.iter().filter_map(|msg| {
+            let Some(ast) = self.scripts.get(&msg.message_type) else {
+                return Some(msg.clone());
+            };
+            match self.run(ast, msg) {
+                Ok(transformed) => Some(transformed),
+                Err(reason) => {
+                    outcome.failed.push(FailedMessage::permanent(msg.id, format!("transform script failed: {reason}")));
+                    None
+                }
+            }
+        }).collect())
+    }
+
+    fn run(&self, ast: &AST, msg: &OutboxMessage) -> Result<OutboxMessage, String> {
+        let body = match serde_json::from_str::<Value>(&msg.body) {
+            Ok(body) => rhai::serde::to_dynamic(body).map_err(|e| e.to_string())?,
+            Err(_) => Dynamic::from(msg.body.clone()),
+        };
+        let headers = match &msg.headers {
+            Some(headers) => rhai::serde::to_dynamic(headers).map_err(|e| e.to_string())?,
+            None => Dynamic::from_map(rhai::Map::new()),
+        };
+
+        let mut scope = Scope::new();
+        scope.push_constant("message_id", msg.message_id.clone());
+        scope.push_constant("message_type", msg.message_type.clone());
+        scope.push("body", body);
+        scope.push("headers", headers);
+        self.engine.run_ast_with_scope(&mut scope, ast).map_err(|e| e.to_string())?;
+
+        let body = scope.get_value::<Dynamic>("body").unwrap_or_default();
+        let body = match body.clone().into_string() {
+            Ok(body) => body,
+            Err(_) => rhai::serde::from_dynamic::<Value>(&body).map_err(|e| e.to_string())?.to_string(),
+        };
+        let headers = scope.get_value::<Dynamic>("headers").unwrap_or_default();
+        let headers = match rhai::serde::from_dynamic::<Value>(&headers).map_err(|e| e.to_string())? {
+            Value::Null => None,
+            Value::Object(headers) if headers.is_empty() && msg.headers.is_none() => None,
+            headers => Some(headers),
+        };
+
+        Ok(OutboxMessage { body, headers, ..msg.clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn transforms(source: &str) -> Transforms {
+        let mut transforms = Transforms::default();
+        transforms.add("test.topic", source).unwrap();
+        transforms
+    }
+
+    #[test]
+    fn test_script_reshapes_body_and_headers() {
+        let transforms = transforms(r#"
+            body.customer_id = body.customerId;
+            body.remove("customerId");
+            body.source = message_type;
+            headers.schema_version = "2";
+        "#);
+        let messages = vec![
+            OutboxMessage { body: json!({ "customerId": 7 }).to_string(), ..OutboxMessage::for_test(1, "") },
+            OutboxMessage { message_type: "other.topic".to_string(), ..OutboxMessage::for_test(2, "") },
+        ];
+        let mut outcome = DispatchOutcome::default();
+
+        let transformed = transforms.apply(&messages, &mut outcome);
+
+        assert!(outcome.failed.is_empty(), "Unexpected failures: {:?}", outcome.failed);
+        assert_eq!(serde_json::from_str::<Value>(&transformed[0].body).unwrap(), json!({ "customer_id": 7, "source": "test.topic" }));
+        assert_eq!(transformed[0].headers, Some(json!({ "schema_version": "2" })));
+        assert_eq!(transformed[1].body, messages[1].body, "Messages without a script should be sent as they are");
+    }
+
+    #[test]
+    fn test_script_errors_are_permanent_failures() {
+        let messages = vec![OutboxMessage::for_test(1, "")];
+
+        for source in [r#"throw "legacy payloads are not supported";"#, "loop { }"] {
+            let mut outcome = DispatchOutcome::default();
+
+            let transformed = transforms(source).apply(&messages, &mut outcome);
+
+            assert!(transformed.is_empty(), "{source}");
+            assert_eq!(outcome.failed.len(), 1, "{source}");
+            assert!(outcome.failed[0].permanent, "{source}");
+            assert!(outcome.failed[0].reason.starts_with("transform script failed: "), "{}", outcome.failed[0].reason);
+        }
+    }
+
+    #[test]
+    fn test_broken_scripts_are_rejected_when_loaded() {
+        let mut transforms = Transforms::default();
+
+        assert!(matches!(transforms.add("test.topic", "body.id = ;"), Err(TransformError::Compile(..))));
+        assert!(transforms.scripts.is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt::Write;
+use std::time::Duration;
+use hmac::{Hmac, KeyInit, Mac};
+use reqwest::StatusCode;
+use sha2::Sha256;
+use tracing::instrument;
+use crate::config::ChannelSettings;
+use crate::messaging::cloudevents;
+use crate::messaging::{redact, DispatchOutcome, FailedMessage};
+use crate::models::OutboxMessage;
+
+/// The header carrying the hex encoded HMAC-SHA256 of the request body.
+pub const SIGNATURE_HEADER: &str = "x-outbox-signature";
+
+const DEFAULT_TIMEOUT_MS: u64 = 10000;
+
+/// POSTs each message body to the webhook URL.
+///
+/// A 2xx response means the message was delivered. Any other 4xx response, apart from a timeout
+/// or throttling response, means the receiver rejected it and it is reported as a permanent
+/// failure. Everything else is retried on the next sweep.
+#[instrument(skip(http_client, url, settings, messages), fields(url = %redact(url)))]
+pub async fn send_messages_to_webhook(
+    http_client: &reqwest::Client,
+    url: &str,
+    settings: &ChannelSettings,
+    messages: &[OutboxMessage],
+) -> DispatchOutcome {
+    let timeout = Duration::from_millis(settings.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+    let mut outcome = DispatchOutcome::default();
+
+    for msg in messages {
+        let mut request = http_client
+            .post(url)
+            .timeout(timeout)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("message_id", &msg.message_id)
+            .header("message_type", &msg.message_type)
+            .body(msg.body.clone());
+
+        if let Some(trace_parent) = &msg.trace_parent {
+            request = request.header("traceparent", trace_parent);
+        }
+        for (name, value) in cloudevents::binary_attributes(settings, msg) {
+            request = request.header(format!("ce-{name}"), value);
+        }
+        if let Some(secret) = &settings.hmac_secret {
+            request = request.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, &msg.body)));
+        }
+
+        match request.send().await {
+            Ok(response) if response.status().is_success() => outcome.sent.push(msg.id),
+            Ok(response) if is_permanent_failure(response.status()) => {
+                outcome.failed.push(FailedMessage::permanent(msg.id, format!("webhook rejected the message with {}", response.status())));
+            }
+            Ok(response) => {
+                outcome.failed.push(FailedMessage::retryable(msg.id, format!("webhook responded with {}", response.status())));
+            }
+            Err(e) => outcome.failed.push(FailedMessage::retryable(msg.id, e.to_string())),
+        }
+    }
+
+    outcome
+}
+
+fn is_permanent_failure(status: StatusCode) -> bool {
+    status.is_client_error() && status != StatusCode::REQUEST_TIMEOUT && status != StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Returns the hex encoded HMAC-SHA256 of the body.
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    mac.finalize().into_bytes().iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+    use std::sync::{Arc, Mutex};
+
+    type Received = Arc<Mutex<Vec<(String, Option<String>, Option<String>)>>>;
+
+    async fn receive(request: HttpRequest, body: String, status: web::Path<u16>, received: web::Data<Received>) -> HttpResponse {
+        let header = |name: &str| request.headers().get(name).map(|v| v.to_str().unwrap().to_string());
+        received.lock().unwrap().push((body, header("message_id"), header(SIGNATURE_HEADER)));
+        HttpResponse::build(actix_web::http::StatusCode::from_u16(*status).unwrap()).finish()
+    }
+
+    /// Starts a local server that responds to `/status/{code}` with that status code.
+    fn start_server() -> (String, Received) {
+        let received: Received = Arc::default();
+        let data = web::Data::new(received.clone());
+        let server = HttpServer::new(move || {
+            App::new().app_data(data.clone()).route("/status/{code}", web::post().to(receive))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .expect("Failed to bind test server");
+        let base_url = format!("http://{}", server.addrs()[0]);
+        tokio::spawn(server.run());
+        (base_url, received)
+    }
+
+    #[tokio::test]
+    async fn test_2xx_is_sent_and_signed() {
+        // --- ARRANGE ---
+        let (base_url, received) = start_server();
+        let settings = ChannelSettings { hmac_secret: Some("secret".to_string()), ..Default::default() };
+        let messages = vec![OutboxMessage::for_test(1, ""), OutboxMessage::for_test(2, "")];
+
+        // --- ACT ---
+        let outcome = send_messages_to_webhook(&reqwest::Client::new(), &format!("{base_url}/status/202"), &settings, &messages).await;
+
+        // --- ASSERT ---
+        assert_eq!(outcome.sent, vec![1, 2]);
+        assert!(outcome.failed.is_empty(), "Unexpected failures: {:?}", outcome.failed);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (body, message_id, signature) = &received[0];
+        assert_eq!(message_id.as_deref(), Some("message-1"));
+        assert_eq!(signature.clone(), Some(format!("sha256={}", sign("secret", body))));
+    }
+
+    #[tokio::test]
+    async fn test_4xx_is_a_permanent_failure() {
+        let (base_url, _received) = start_server();
+
+        let outcome = send_messages_to_webhook(&reqwest::Client::new(), &format!("{base_url}/status/422"), &ChannelSettings::default(), &[OutboxMessage::for_test(1, "")]).await;
+
+        assert!(outcome.sent.is_empty());
+        assert_eq!(outcome.failed.len(), 1);
+        assert!(outcome.failed[0].permanent, "4xx should not be retried");
+    }
+
+    #[tokio::test]
+    async fn test_5xx_and_429_are_retried() {
+        let (base_url, _received) = start_server();
+
+        for status in [500, 429] {
+            let url = format!("{base_url}/status/{status}");
+            let outcome = send_messages_to_webhook(&reqwest::Client::new(), &url, &ChannelSettings::default(), &[OutboxMessage::for_test(1, "")]).await;
+
+            assert!(outcome.sent.is_empty());
+            assert!(!outcome.failed[0].permanent, "{status} should be retried");
+        }
+    }
+
+    #[test]
+    fn test_sign_matches_known_vector() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
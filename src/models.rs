@@ -1,8 +1,9 @@
 use chrono::{Utc, DateTime};
+use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
 #[allow(dead_code)]
-#[derive(Debug, FromRow)]
+#[derive(Debug, Clone, FromRow, Serialize)]
 pub struct OutboxMessage {
     pub id: i64,
     pub message_id: String,
@@ -12,4 +13,69 @@ pub struct OutboxMessage {
     pub timestamp: DateTime<Utc>,
     pub body: String,
     pub trace_parent: Option<String>,
+    pub partition_key: Option<String>,
+    pub headers: Option<serde_json::Value>,
+}
+
+impl OutboxMessage {
+    /// Returns the key used to partition this message, falling back to the message id.
+    pub fn record_key(&self) -> &str {
+        self.partition_key.as_deref().unwrap_or(&self.message_id)
+    }
+}
+
+/// An outbox column that can be copied onto a message, e.g. as an SNS message attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxColumn {
+    MessageId,
+    MessageType,
+    ChannelAddress,
+    TraceParent,
+    PartitionKey,
+}
+
+impl OutboxColumn {
+    /// The name of the column.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutboxColumn::MessageId => "message_id",
+            OutboxColumn::MessageType => "message_type",
+            OutboxColumn::ChannelAddress => "channel_address",
+            OutboxColumn::TraceParent => "trace_parent",
+            OutboxColumn::PartitionKey => "partition_key",
+        }
+    }
+}
+
+impl OutboxMessage {
+    /// Returns the value of the column, if it is set.
+    pub fn column(&self, column: OutboxColumn) -> Option<&str> {
+        match column {
+            OutboxColumn::MessageId => Some(&self.message_id),
+            OutboxColumn::MessageType => Some(&self.message_type),
+            OutboxColumn::ChannelAddress => Some(&self.channel_address),
+            OutboxColumn::TraceParent => self.trace_parent.as_deref(),
+            OutboxColumn::PartitionKey => self.partition_key.as_deref(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl OutboxMessage {
+    /// Builds an undispatched message for tests that do not need a database.
+    pub fn for_test(id: i64, channel_address: &str) -> Self {
+        OutboxMessage {
+            id,
+            message_id: format!("message-{id}"),
+            message_type: "test.topic".to_string(),
+            channel_address: channel_address.to_string(),
+            dispatched: None,
+            timestamp: Utc::now(),
+            body: format!(r#"{{ "id": {id} }}"#),
+            trace_parent: Some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".to_string()),
+            partition_key: None,
+            headers: None,
+        }
+    }
 }
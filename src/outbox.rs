@@ -9,6 +9,7 @@ pub async fn get_distinct_pending_topics(pool: &PgPool) -> Result<Vec<String>, s
         SELECT DISTINCT message_type
         FROM core.outbox
         WHERE dispatched is null
+            And dead_lettered is null
         "#,
     )
         .fetch_all(pool)
@@ -30,9 +31,10 @@ pub async fn get_pending_messages(
 ) -> Result<Vec<OutboxMessage>, sqlx::Error> {
     let messages = query_as::<_, OutboxMessage>(
         r#"
-        SELECT id, message_id, message_type, channel_address, dispatched, timestamp, body, trace_parent
+        SELECT id, message_id, message_type, channel_address, dispatched, timestamp, body, trace_parent, partition_key, headers
         FROM core.outbox
         WHERE dispatched is null
+            And dead_lettered is null
             And message_type = $1
         ORDER BY timestamp
         LIMIT $2
@@ -69,3 +71,135 @@ pub async fn mark_messages_as_sent(
 
     Ok(())
 }
+
+/// Marks messages as dead-lettered so that they are no longer picked up by the sweeper.
+///
+/// `reasons` must line up with `message_ids`, each reason is stored against its message.
+pub async fn mark_messages_as_dead_lettered(
+    db_pool: &PgPool,
+    message_ids: Vec<i64>,
+    reasons: Vec<String>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE core.outbox
+        SET dead_lettered = NOW(), dead_letter_reason = failures.reason
+        FROM UNNEST($1::BIGINT[], $2::TEXT[]) AS failures(id, reason)
+        WHERE core.outbox.id = failures.id
+        "#,
+    )
+        .bind(message_ids)
+        .bind(reasons)
+        .execute(db_pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Fetches a batch of pending messages that have not yet been delivered to a routed destination.
+pub async fn get_pending_deliveries(
+    db_pool: &PgPool,
+    topic: &str,
+    channel_address: &str,
+    batch_size: &i32,
+) -> Result<Vec<OutboxMessage>, sqlx::Error> {
+    let messages = query_as::<_, OutboxMessage>(
+        r#"
+        SELECT id, message_id, message_type, channel_address, dispatched, timestamp, body, trace_parent, partition_key, headers
+        FROM core.outbox
+        WHERE dispatched is null
+            And dead_lettered is null
+            And message_type = $1
+            And NOT EXISTS (
+                SELECT 1 FROM core.outbox_deliveries
+                WHERE outbox_id = core.outbox.id
+                    And channel_address = $2
+            )
+        ORDER BY timestamp
+        LIMIT $3
+        FOR UPDATE SKIP LOCKED
+        "#,
+    )
+        .bind(topic)
+        .bind(channel_address)
+        .bind(batch_size)
+        .fetch_all(db_pool)
+        .await?;
+
+    Ok(messages)
+}
+
+/// Records that messages have been delivered to a routed destination.
+pub async fn mark_deliveries_as_sent(
+    db_pool: &PgPool,
+    channel_address: &str,
+    message_ids: Vec<i64>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO core.outbox_deliveries (outbox_id, channel_address, dispatched)
+        SELECT id, $2, NOW() FROM UNNEST($1::BIGINT[]) AS sent(id)
+        ON CONFLICT (outbox_id, channel_address) DO NOTHING
+        "#,
+    )
+        .bind(message_ids)
+        .bind(channel_address)
+        .execute(db_pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Records that messages can never be delivered to a routed destination.
+///
+/// `reasons` must line up with `message_ids`, each reason is stored against its message.
+pub async fn mark_deliveries_as_dead_lettered(
+    db_pool: &PgPool,
+    channel_address: &str,
+    message_ids: Vec<i64>,
+    reasons: Vec<String>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO core.outbox_deliveries (outbox_id, channel_address, dead_lettered, dead_letter_reason)
+        SELECT id, $3, NOW(), reason FROM UNNEST($1::BIGINT[], $2::TEXT[]) AS failures(id, reason)
+        ON CONFLICT (outbox_id, channel_address) DO NOTHING
+        "#,
+    )
+        .bind(message_ids)
+        .bind(reasons)
+        .bind(channel_address)
+        .execute(db_pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Marks routed messages as dispatched once every one of their destinations has been delivered
+/// to or dead-lettered.
+pub async fn complete_routed_messages(
+    db_pool: &PgPool,
+    topic: &str,
+    channel_addresses: &[String],
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE core.outbox
+        SET dispatched = NOW()
+        WHERE dispatched is null
+            And dead_lettered is null
+            And message_type = $1
+            And (
+                SELECT COUNT(DISTINCT channel_address) FROM core.outbox_deliveries
+                WHERE outbox_id = core.outbox.id
+                    And channel_address = Any($2)
+            ) = (SELECT COUNT(DISTINCT address) FROM UNNEST($2::VARCHAR[]) AS routes(address))
+        "#,
+    )
+        .bind(topic)
+        .bind(channel_addresses)
+        .execute(db_pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,365 @@
+use serde::Deserialize;
+use serde_json::Value;
+use crate::models::OutboxMessage;
+
+/// A `channel_address` that asks for the destination to be picked by the routing rules, as an
+/// empty address does.
+pub const ROUTING_PLACEHOLDER: &str = "route://";
+
+/// A rule that sends matching messages to a destination.
+///
+/// ```toml
+/// [[rules]]
+/// message_type = "order.created"
+/// when = "$.region == 'eu' && headers.tenant_id != 'test'"
+/// destination = "https://sqs.eu-west-1.amazonaws.com/000000000000/eu-orders"
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "RawRoutingRule")]
+pub struct RoutingRule {
+    pub message_type: Option<String>,
+    pub when: Condition,
+    pub destination: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRoutingRule {
+    message_type: Option<String>,
+    when: String,
+    destination: String,
+}
+
+impl TryFrom<RawRoutingRule> for RoutingRule {
+    type Error = RuleError;
+
+    fn try_from(raw: RawRoutingRule) -> Result<Self, Self::Error> {
+        if raw.destination.is_empty() || raw.destination == ROUTING_PLACEHOLDER {
+            return Err(RuleError::InvalidDestination(raw.destination));
+        }
+        Ok(RoutingRule { message_type: raw.message_type, when: raw.when.parse()?, destination: raw.destination })
+    }
+}
+
+impl RoutingRule {
+    pub fn matches(&self, msg: &OutboxMessage) -> bool {
+        self.message_type.as_ref().is_none_or(|message_type| *message_type == msg.message_type) && self.when.matches(msg)
+    }
+}
+
+/// Whether the message asks for its destination to be picked by the routing rules.
+pub fn needs_routing(msg: &OutboxMessage) -> bool {
+    msg.channel_address.is_empty() || msg.channel_address == ROUTING_PLACEHOLDER
+}
+
+/// Why a routing rule could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RuleError {
+    #[error("unexpected {0:?} in routing rule")]
+    Unexpected(String),
+    #[error("routing rule ended early, expected {0}")]
+    UnexpectedEnd(&'static str),
+    #[error("unterminated string in routing rule")]
+    UnterminatedString,
+    #[error("paths must start with `$.` for the body or `headers.` for the headers, found {0:?}")]
+    InvalidPath(String),
+    #[error("{0:?} is not a valid routing rule destination")]
+    InvalidDestination(String),
+}
+
+/// A condition over the body and headers of a message.
+///
+/// Conditions compare a path with a literal, `$.region == 'eu'`, and can be combined with `&&`
+/// and `||`, where `&&` binds tighter. Paths start at the JSON body with `$.` or at the headers
+/// with `headers.`. Literals are single or double quoted strings, numbers, `true`, `false` and
+/// `null`. A path that does not exist only matches `== null`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Compare { path: Path, op: Operator, value: Value },
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Path {
+    Body(Vec<String>),
+    Headers(Vec<String>),
+}
+
+impl Path {
+    fn resolve<'m>(&self, msg: &'m OutboxMessage, body: Option<&'m Value>) -> Option<&'m Value> {
+        let (root, keys) = match self {
+            Path::Body(keys) => (body?, keys),
+            Path::Headers(keys) => (msg.headers.as_ref()?, keys),
+        };
+        keys.iter().try_fold(root, |value, key| match value {
+            Value::Object(map) => map.get(key),
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => None,
+        })
+    }
+}
+
+impl Condition {
+    pub fn matches(&self, msg: &OutboxMessage) -> bool {
+        let body = serde_json::from_str::<Value>(&msg.body).ok();
+        self.evaluate(msg, body.as_ref())
+    }
+
+    fn evaluate(&self, msg: &OutboxMessage, body: Option<&Value>) -> bool {
+        match self {
+            Condition::All(conditions) => conditions.iter().all(|c| c.evaluate(msg, body)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.evaluate(msg, body)),
+            Condition::Compare { path, op, value } => {
+                let actual = path.resolve(msg, body).unwrap_or(&Value::Null);
+                match op {
+                    Operator::Eq => actual == value,
+                    Operator::Ne => actual != value,
+                    _ => match (actual.as_f64(), value.as_f64()) {
+                        (Some(actual), Some(value)) => match op {
+                            Operator::Lt => actual < value,
+                            Operator::Le => actual <= value,
+                            Operator::Gt => actual > value,
+                            _ => actual >= value,
+                        },
+                        _ => false,
+                    },
+                }
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Condition {
+    type Err = RuleError;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(rule)?;
+        let mut parser = Parser { tokens: &tokens, position: 0 };
+        let condition = parser.any()?;
+        match parser.tokens.get(parser.position) {
+            Some(token) => Err(RuleError::Unexpected(token.to_string())),
+            None => Ok(condition),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Literal(Value),
+    Operator(Operator),
+    And,
+    Or,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{word}"),
+            Token::Literal(value) => write!(f, "{value}"),
+            Token::Operator(op) => write!(f, "{op:?}"),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+        }
+    }
+}
+
+fn tokenize(rule: &str) -> Result<Vec<Token>, RuleError> {
+    let mut tokens = Vec::new();
+    let mut chars = rule.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '\'' | '"' => {
+                let mut literal = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, end)) if end == c => break,
+                        Some((_, next)) => literal.push(next),
+                        None => return Err(RuleError::UnterminatedString),
+                    }
+                }
+                Token::Literal(Value::String(literal))
+            }
+            '&' | '|' | '=' | '!' | '<' | '>' => {
+                let next = chars.next_if(|(_, next)| matches!((c, next), ('&', '&') | ('|', '|') | (_, '=')));
+                match (c, next.map(|(_, next)| next)) {
+                    ('&', Some('&')) => Token::And,
+                    ('|', Some('|')) => Token::Or,
+                    ('=', Some('=')) => Token::Operator(Operator::Eq),
+                    ('!', Some('=')) => Token::Operator(Operator::Ne),
+                    ('<', Some('=')) => Token::Operator(Operator::Le),
+                    ('>', Some('=')) => Token::Operator(Operator::Ge),
+                    ('<', None) => Token::Operator(Operator::Lt),
+                    ('>', None) => Token::Operator(Operator::Gt),
+                    _ => return Err(RuleError::Unexpected(c.to_string())),
+                }
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, next)) = chars.next_if(|(_, next)| !next.is_whitespace() && !"'\"&|=!<>".contains(*next)) {
+                    end = i + next.len_utf8();
+                }
+                let word = &rule[start..end];
+                match word {
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    _ => match word.parse::<serde_json::Number>() {
+                        Ok(number) => Token::Literal(Value::Number(number)),
+                        Err(_) => Token::Word(word.to_string()),
+                    },
+                }
+            }
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'t> {
+    tokens: &'t [Token],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self, expected: &'static str) -> Result<&Token, RuleError> {
+        let token = self.tokens.get(self.position).ok_or(RuleError::UnexpectedEnd(expected))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn next_is(&mut self, token: &Token) -> bool {
+        let matches = self.tokens.get(self.position) == Some(token);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn any(&mut self) -> Result<Condition, RuleError> {
+        let mut conditions = vec![self.all()?];
+        while self.next_is(&Token::Or) {
+            conditions.push(self.all()?);
+        }
+        Ok(if conditions.len() == 1 { conditions.remove(0) } else { Condition::Any(conditions) })
+    }
+
+    fn all(&mut self) -> Result<Condition, RuleError> {
+        let mut conditions = vec![self.compare()?];
+        while self.next_is(&Token::And) {
+            conditions.push(self.compare()?);
+        }
+        Ok(if conditions.len() == 1 { conditions.remove(0) } else { Condition::All(conditions) })
+    }
+
+    fn compare(&mut self) -> Result<Condition, RuleError> {
+        let path = match self.next("a path")? {
+            Token::Word(word) => parse_path(word)?,
+            token => return Err(RuleError::Unexpected(token.to_string())),
+        };
+        let op = match self.next("an operator")? {
+            Token::Operator(op) => *op,
+            token => return Err(RuleError::Unexpected(token.to_string())),
+        };
+        let value = match self.next("a value")? {
+            Token::Literal(value) => value.clone(),
+            token => return Err(RuleError::Unexpected(token.to_string())),
+        };
+        Ok(Condition::Compare { path, op, value })
+    }
+}
+
+fn parse_path(word: &str) -> Result<Path, RuleError> {
+    let keys = |rest: &str| -> Result<Vec<String>, RuleError> {
+        let keys: Vec<String> = rest.split('.').map(str::to_string).collect();
+        if keys.iter().any(String::is_empty) {
+            return Err(RuleError::InvalidPath(word.to_string()));
+        }
+        Ok(keys)
+    };
+
+    if let Some(rest) = word.strip_prefix("$.") {
+        Ok(Path::Body(keys(rest)?))
+    } else if let Some(rest) = word.strip_prefix("headers.") {
+        Ok(Path::Headers(keys(rest)?))
+    } else {
+        Err(RuleError::InvalidPath(word.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(body: Value, headers: Option<Value>) -> OutboxMessage {
+        OutboxMessage { body: body.to_string(), headers, ..OutboxMessage::for_test(1, "") }
+    }
+
+    #[test]
+    fn test_conditions_match() {
+        let msg = message(
+            json!({ "region": "eu", "total": 120.5, "customer": { "tier": "gold" }, "items": ["a", "b"] }),
+            Some(json!({ "tenant_id": "acme" })),
+        );
+
+        let cases = vec![
+            ("$.region == 'eu'", true),
+            ("$.region == \"us\"", false),
+            ("$.region != 'us'", true),
+            ("$.total > 100", true),
+            ("$.total <= 100", false),
+            ("$.customer.tier == 'gold' && headers.tenant_id == 'acme'", true),
+            ("$.region == 'us' || headers.tenant_id == 'acme'", true),
+            ("$.region == 'us' || $.region == 'eu' && $.total < 100", false),
+            ("$.items.1 == 'b'", true),
+            ("$.missing == null", true),
+            ("$.region > 1", false),
+        ];
+
+        for (rule, expected) in cases {
+            let condition: Condition = rule.parse().unwrap_or_else(|e| panic!("{rule}: {e}"));
+            assert_eq!(condition.matches(&msg), expected, "{rule}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_rules_are_rejected() {
+        let cases = vec![
+            ("region == 'eu'", RuleError::InvalidPath("region".to_string())),
+            ("$.region == 'eu", RuleError::UnterminatedString),
+            ("$.region ==", RuleError::UnexpectedEnd("a value")),
+            ("$.region = 'eu'", RuleError::Unexpected("=".to_string())),
+            ("$.region == 'eu' 'us'", RuleError::Unexpected("\"us\"".to_string())),
+            ("$..region == 'eu'", RuleError::InvalidPath("$..region".to_string())),
+        ];
+
+        for (rule, expected) in cases {
+            assert_eq!(rule.parse::<Condition>(), Err(expected), "{rule}");
+        }
+    }
+
+    #[test]
+    fn test_rules_are_validated_when_loaded() {
+        let rule: RoutingRule = toml::from_str("message_type = 'order.created'\nwhen = \"$.region == 'eu'\"\ndestination = 'kafka://eu-orders'").unwrap();
+        assert!(rule.matches(&OutboxMessage { message_type: "order.created".to_string(), ..message(json!({ "region": "eu" }), None) }));
+        assert!(!rule.matches(&message(json!({ "region": "eu" }), None)), "The message type should have to match");
+
+        let invalid = toml::from_str::<RoutingRule>("when = \"region == 'eu'\"\ndestination = 'kafka://eu-orders'");
+        assert!(invalid.unwrap_err().to_string().contains("paths must start with"));
+    }
+}
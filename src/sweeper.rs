@@ -1,18 +1,50 @@
-use crate::{messaging, outbox};
-use aws_sdk_sqs::Client as SqsClient;
-use aws_sdk_sns::Client as SnsClient;
+use crate::messaging::{redact, shadow, Channel, DispatchOutcome, Dispatcher, FailedMessage};
+use crate::models::OutboxMessage;
+use crate::outbox;
 use sqlx::PgPool;
-use tracing::{error, info, instrument, Span};
+use tracing::{debug, error, info, instrument, warn, Instrument, Span};
 
 // Helper function to mark messages as sent and log the result
-async fn mark_and_log_sent(db_pool: &sqlx::PgPool, topic: &str, messages: &[crate::models::OutboxMessage], messages_found: usize) {
-    let message_ids: Vec<i64> = messages.iter().map(|m| m.id).collect();
-    match outbox::mark_messages_as_sent(db_pool, message_ids).await {
+//
+// When `route` is set the messages are only marked as delivered to that routed destination.
+// `destination` is where the messages were actually sent, which differs from the route once the
+// channel has failed over.
+async fn mark_and_log_sent(db_pool: &sqlx::PgPool, topic: &str, route: Option<&str>, destination: &str, message_ids: Vec<i64>) {
+    let messages_sent = message_ids.len();
+    let marked = match route {
+        Some(channel_address) => outbox::mark_deliveries_as_sent(db_pool, channel_address, message_ids).await,
+        None => outbox::mark_messages_as_sent(db_pool, message_ids).await,
+    };
+    match marked {
         Ok(_) => {
-            info!(%topic, messages_sent = messages_found, "Successfully sent and marked messages.");
+            info!(%topic, route = route.map(redact).as_deref(), destination = %redact(destination), messages_sent, "Successfully sent and marked messages.");
         }
         Err(e) => {
-            error!(%topic, "Error marking messages: {}. These messages WILL be re-sent.", e);
+            error!(%topic, route = route.map(redact).as_deref(), destination = %redact(destination), "Error marking messages: {}. These messages WILL be re-sent.", e);
+        }
+    }
+}
+
+// Helper function to dead-letter messages that can never be sent and log the result
+//
+// When `route` is set the messages are only dead-lettered for that routed destination.
+// `destination` is where the messages were rejected, if they were sent anywhere.
+async fn dead_letter_and_log(db_pool: &sqlx::PgPool, topic: &str, route: Option<&str>, destination: Option<&str>, failed: Vec<FailedMessage>) {
+    let messages_dead_lettered = failed.len();
+    for message in &failed {
+        error!(%topic, route = route.map(redact).as_deref(), destination = destination.map(redact).as_deref(), id = message.id, reason = %message.reason, "Message can not be sent, dead-lettering it.");
+    }
+    let (message_ids, reasons) = failed.into_iter().map(|f| (f.id, f.reason)).unzip();
+    let dead_lettered = match route {
+        Some(channel_address) => outbox::mark_deliveries_as_dead_lettered(db_pool, channel_address, message_ids, reasons).await,
+        None => outbox::mark_messages_as_dead_lettered(db_pool, message_ids, reasons).await,
+    };
+    match dead_lettered {
+        Ok(_) => {
+            info!(%topic, route = route.map(redact).as_deref(), destination = destination.map(redact).as_deref(), messages_dead_lettered, "Dead-lettered messages.");
+        }
+        Err(e) => {
+            error!(%topic, route = route.map(redact).as_deref(), "Error dead-lettering messages: {}. These messages WILL be re-sent.", e);
         }
     }
 }
@@ -20,8 +52,7 @@ async fn mark_and_log_sent(db_pool: &sqlx::PgPool, topic: &str, messages: &[crat
 #[instrument(skip_all, fields(topics_needing_dispatch=0))]
 pub async fn sweep_outbox_and_send(
     db_pool: &PgPool,
-    sqs_client: &SqsClient,
-    sns_client: &SnsClient,
+    dispatcher: &Dispatcher,
     batch_size: &i32,
 ) -> Result<(), sqlx::Error> {
     info!("Checking outbox for pending messages...");
@@ -36,7 +67,10 @@ pub async fn sweep_outbox_and_send(
     Span::current().record("topics_needing_dispatch", topics_needing_dispatch);
 
     for topic in topics {
-        sweep_channel(db_pool, sqs_client, sns_client, batch_size, &topic).await?;
+        match dispatcher.routes(&topic) {
+            Some(routes) => sweep_routes(db_pool, dispatcher, batch_size, &topic, routes).await?,
+            None => sweep_channel(db_pool, dispatcher, batch_size, &topic).await?,
+        }
     }
 
     info!("Outbox sweep complete for all topics.");
@@ -47,14 +81,13 @@ pub async fn sweep_outbox_and_send(
 #[instrument(skip_all, fields(messages_found=0))]
 pub async fn sweep_channel(
     db_pool: &PgPool,
-    sqs_client: &SqsClient,
-    sns_client: &SnsClient,
+    dispatcher: &Dispatcher,
     batch_size: &i32,
     channel_name: &str,
 ) -> Result<(), sqlx::Error>
 {
     Span::current().record("channel_name", channel_name);
-    let messages = outbox::get_pending_messages(db_pool, &channel_name, &batch_size).await?;
+    let messages = outbox::get_pending_messages(db_pool, channel_name, batch_size).await?;
 
     let messages_found = messages.len();
     if messages_found == 0 {
@@ -64,40 +97,246 @@ pub async fn sweep_channel(
     info!(messages_found, "Found messages to send.");
     Span::current().record("messages_found", messages_found);
 
-    let channel_address = messages[0].channel_address.clone();
+    // Messages without a channel address are sent wherever the routing rules pick for them
+    let mut destinations: Vec<(&str, Vec<OutboxMessage>)> = Vec::new();
+    let mut unroutable = Vec::new();
+    for msg in &messages {
+        match dispatcher.destination(msg) {
+            Some(destination) => match destinations.iter_mut().find(|(address, _)| *address == destination) {
+                Some((_, batch)) => batch.push(msg.clone()),
+                None => destinations.push((destination, vec![msg.clone()])),
+            },
+            None => unroutable.push(FailedMessage::permanent(msg.id, "no routing rule matched the message")),
+        }
+    }
+    if !unroutable.is_empty() {
+        dead_letter_and_log(db_pool, channel_name, None, None, unroutable).await;
+    }
 
-    if let Some((channel_type, address)) = channel_address.split_once("::") {
-        info!(channel_type, "Channel Selected");
-        match messaging::send_messages_to_sns(sns_client, address.to_string(), &messages).await{
-            Ok(_) =>{
-                mark_and_log_sent(db_pool, &channel_name, &messages, messages_found).await;
-            }
-            Err(e) =>{
-                error!(err = ?e, %channel_name, %channel_type, "Failed to send messages to SNS");
-            }
+    let mut messages_sent = 0;
+    for (channel_address, batch) in &destinations {
+        messages_sent += dispatch_and_record(db_pool, dispatcher, channel_name, channel_address, None, batch).await;
+    }
+    info!("Outbox sweep complete for channel {}. Sent {} messages.", channel_name, messages_sent);
+
+    Ok(())
+}
+
+/// Fans the pending messages of a routed message type out to each of its destinations.
+///
+/// Every destination is tracked in `core.outbox_deliveries`, so a failure at one destination only
+/// causes that destination to be retried. A message is marked as dispatched once all of its
+/// destinations have been delivered to or dead-lettered.
+#[instrument(skip_all, fields(routes = routes.len()))]
+pub async fn sweep_routes(
+    db_pool: &PgPool,
+    dispatcher: &Dispatcher,
+    batch_size: &i32,
+    channel_name: &str,
+    routes: &[String],
+) -> Result<(), sqlx::Error> {
+    for route in routes {
+        let messages = outbox::get_pending_deliveries(db_pool, channel_name, route, batch_size).await?;
+        if messages.is_empty() {
+            continue;
         }
+        info!(messages_found = messages.len(), route = %redact(route), "Found messages to send.");
+
+        let messages_sent = dispatch_and_record(db_pool, dispatcher, channel_name, route, Some(route), &messages).await;
+        info!("Outbox sweep complete for channel {} to {}. Sent {} messages.", channel_name, redact(route), messages_sent);
+    }
+
+    let messages_completed = outbox::complete_routed_messages(db_pool, channel_name, routes).await?;
+    if messages_completed > 0 {
+        info!(%channel_name, messages_completed, "Messages delivered to every route.");
+    }
+
+    Ok(())
+}
+
+/// Sends the messages to the channel address and records which were sent and which were
+/// dead-lettered, returning how many were sent.
+async fn dispatch_and_record(
+    db_pool: &PgPool,
+    dispatcher: &Dispatcher,
+    channel_name: &str,
+    channel_address: &str,
+    route: Option<&str>,
+    messages: &[OutboxMessage],
+) -> usize {
+    let Some((destination, messages, outcome)) = dispatch_with_failover(dispatcher, channel_name, channel_address, messages).await else {
+        return 0;
+    };
+
+    send_to_shadow(dispatcher, channel_name, channel_address, messages, &outcome);
+
+    let channel_type = Channel::parse(destination).kind();
+    let (permanent, retryable): (Vec<_>, Vec<_>) = outcome.failed.into_iter().partition(|f| f.permanent);
+    for failed in &retryable {
+        warn!(id = failed.id, reason = %failed.reason, %channel_name, %channel_type, destination = %redact(destination), "Message was not accepted, it will be retried.");
+    }
+    let messages_sent = outcome.sent.len();
+    if messages_sent > 0 {
+        mark_and_log_sent(db_pool, channel_name, route, destination, outcome.sent).await;
+    }
+    if !permanent.is_empty() {
+        dead_letter_and_log(db_pool, channel_name, route, Some(destination), permanent).await;
+    }
+    messages_sent
+}
+
+/// Copies a sample of the batch to the channel's shadow destination, if it has one.
+///
+/// The copy is sent in the background, so the shadow destination can never hold up or fail the
+/// primary send. It goes through the shadow destination's own rate limit and circuit breaker, and
+/// the sample is dropped when `shadow::MAX_IN_FLIGHT` copies are already being sent. How many of
+/// the sampled messages each destination accepted is logged, so the two can be compared.
+fn send_to_shadow(dispatcher: &Dispatcher, channel_name: &str, channel_address: &str, messages: &[OutboxMessage], primary: &DispatchOutcome) {
+    let settings = dispatcher.settings(channel_address);
+    let Some(shadow_address) = settings.shadow_address.clone() else {
+        return;
+    };
+    let sample: Vec<OutboxMessage> = shadow::sample(settings, messages).into_iter().cloned().collect();
+    if sample.is_empty() {
+        return;
     }
-    else {
-        match messaging::send_messages_to_sqs(sqs_client, channel_address, &messages).await {
-            Ok(_) => {
-                mark_and_log_sent(db_pool, &channel_name, &messages, messages_found).await;
+
+    let Some(slot) = dispatcher.try_shadow_slot() else {
+        warn!(%channel_name, shadow_address = %redact(&shadow_address), messages_dropped = sample.len(), "Too many shadow sends in flight, dropping the sample.");
+        return;
+    };
+    let Some(sample) = rate_limited(dispatcher, channel_name, &shadow_address, &sample) else {
+        return;
+    };
+    if !dispatcher.circuit_allows(&shadow_address) {
+        debug!(%channel_name, shadow_address = %redact(&shadow_address), "Circuit is open, skipping shadow destination.");
+        return;
+    }
+
+    let sample = sample.to_vec();
+    let messages_sampled = sample.len();
+    let primary_sent = sample.iter().filter(|msg| primary.sent.contains(&msg.id)).count();
+    let (dispatcher, channel_name, channel_address) = (dispatcher.clone(), channel_name.to_string(), channel_address.to_string());
+    tokio::spawn(async move {
+        let shadow_sent = dispatch_to(&dispatcher, &channel_name, &shadow_address, &sample).await.map_or(0, |outcome| outcome.sent.len());
+        info!(%channel_name, channel_address = %redact(&channel_address), shadow_address = %redact(&shadow_address), messages_sampled, primary_sent, shadow_sent, "Shadow dispatch complete.");
+        drop(slot);
+    }.in_current_span());
+}
+
+/// Sends the messages to the channel address, or to its failover destination once the channel's
+/// circuit is open or its retry budget has run out.
+///
+/// Returns the destination of the last call, the messages it was given and its outcome, or `None`
+/// when no call was made. A call that rejects every message counts as failed, so it is retried and
+/// failed over like a call that returned an error. Every call, including retries and the failover,
+/// is subject to its destination's rate limit. Its tokens are taken before the circuit is checked,
+/// so a circuit is only ever let through to its trial call when that call is made. A rate limited
+/// channel leaves its messages pending, unless an earlier call to it has already failed, in which
+/// case they are failed over. Channels whose calls keep failing are skipped until their circuit's
+/// cooldown has passed, and the trial call made after that switches back to the channel if it has
+/// recovered.
+async fn dispatch_with_failover<'a, 'm>(
+    dispatcher: &'a Dispatcher,
+    channel_name: &str,
+    channel_address: &'a str,
+    messages: &'m [OutboxMessage],
+) -> Option<(&'a str, &'m [OutboxMessage], DispatchOutcome)> {
+    let settings = dispatcher.settings(channel_address);
+    let mut rejected = None;
+    for attempt in 0..settings.retry_budget.unwrap_or(1).max(1) {
+        let Some(batch) = rate_limited(dispatcher, channel_name, channel_address, messages) else {
+            if attempt == 0 {
+                return None;
             }
-            Err(e) => {
-                error!(err = ?e, %channel_name, "Failed to send messages to SQS");
+            break;
+        };
+        if !dispatcher.circuit_allows(channel_address) {
+            debug!(%channel_name, channel_address = %redact(channel_address), "Circuit is open, skipping channel.");
+            break;
+        }
+        match dispatch_to(dispatcher, channel_name, channel_address, batch).await {
+            Some(outcome) if outcome.rejected_every_message() => rejected = Some((channel_address, batch, outcome)),
+            Some(outcome) => return Some((channel_address, batch, outcome)),
+            None => {}
+        }
+    }
+
+    let Some(failover_address) = settings.failover_address.as_deref() else {
+        return rejected;
+    };
+    let Some(batch) = rate_limited(dispatcher, channel_name, failover_address, messages) else {
+        return rejected;
+    };
+    if !dispatcher.circuit_allows(failover_address) {
+        debug!(%channel_name, failover_address = %redact(failover_address), "Circuit is open, skipping failover destination.");
+        return rejected;
+    }
+    warn!(%channel_name, channel_address = %redact(channel_address), failover_address = %redact(failover_address), "Failing over to the secondary destination.");
+    match dispatch_to(dispatcher, channel_name, failover_address, batch).await {
+        Some(outcome) => Some((failover_address, batch, outcome)),
+        None => rejected,
+    }
+}
+
+/// Takes the destination's rate limit tokens for a call, returning the messages that fit within
+/// them, or `None` when nothing can be sent to the destination yet.
+fn rate_limited<'m>(dispatcher: &Dispatcher, channel_name: &str, destination: &str, messages: &'m [OutboxMessage]) -> Option<&'m [OutboxMessage]> {
+    match dispatcher.acquire(destination, messages.len()) {
+        Ok(allowed) => {
+            if allowed < messages.len() {
+                info!(%channel_name, destination = %redact(destination), messages_deferred = messages.len() - allowed, "Rate limit reached, deferring messages.");
             }
+            Some(&messages[..allowed])
+        }
+        Err(wait) => {
+            let rate_limit_wait_ms = wait.as_millis() as u64;
+            info!(%channel_name, destination = %redact(destination), rate_limit_wait_ms, "Channel is rate limited, leaving messages pending.");
+            None
         }
     }
-    info!("Outbox sweep complete for channel {}. Sent {} messages.", channel_name, messages_found);
+}
 
-    Ok(())
+/// Makes a single call to the destination, recording its result against the destination's circuit.
+///
+/// A call that returns an error or rejects every message counts as a failure.
+async fn dispatch_to(dispatcher: &Dispatcher, channel_name: &str, destination: &str, messages: &[OutboxMessage]) -> Option<DispatchOutcome> {
+    let channel_type = Channel::parse(destination).kind();
+    info!(channel_type, destination = %redact(destination), "Channel Selected");
+
+    let outcome = match dispatcher.dispatch(destination, messages).await {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            error!(err = ?e, %channel_name, %channel_type, destination = %redact(destination), "Failed to send messages to {}", channel_type);
+            record_failure(dispatcher, channel_name, destination);
+            return None;
+        }
+    };
+
+    if outcome.rejected_every_message() {
+        let messages_rejected = outcome.failed.len();
+        warn!(%channel_name, %channel_type, destination = %redact(destination), messages_rejected, "Every message was rejected, counting the call as failed.");
+        record_failure(dispatcher, channel_name, destination);
+    } else if dispatcher.record_success(destination) {
+        info!(%channel_name, destination = %redact(destination), "Closed circuit, the channel has recovered.");
+    }
+    Some(outcome)
+}
+
+fn record_failure(dispatcher: &Dispatcher, channel_name: &str, destination: &str) {
+    if dispatcher.record_failure(destination) {
+        warn!(%channel_name, destination = %redact(destination), "Opened circuit, the channel will be skipped until its cooldown has passed.");
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::Config;
+    use crate::config::{ChannelConfig, Config};
     use crate::clients::setup_aws_clients;
-    use sqlx::{Executor, PgPool};
+    use crate::messaging::schema::Schemas;
+    use crate::messaging::transform::Transforms;
+    use sqlx::{Executor, PgPool, Row};
     use uuid::{ Uuid};
     use crate::models::OutboxMessage; // Import this
 
@@ -121,11 +360,29 @@ mod tests {
         message_id
     }
 
+    // Helper function to insert a test message with its own body, leaving the routing rules to pick its destination
+    async fn insert_test_message_with_body(pool: &PgPool, body: &str) -> String {
+        let message_id = Uuid::new_v4().to_string();
+        sqlx::query(
+            r#"
+                INSERT INTO core.outbox (message_id, message_type, channel_address, timestamp, body)
+            VALUES ($1, 'test.topic', 'route://', NOW(), $2)
+            "#,
+        )
+            .bind(&message_id)
+            .bind(body)
+            .execute(pool)
+            .await
+            .expect("Failed to insert test message");
+
+        message_id
+    }
+
     // Helper function to get a message
     async fn get_message(pool: &PgPool, message_id: String) -> Option<OutboxMessage> {
         // Use query_as to get the full struct
         sqlx::query_as::<_, OutboxMessage>(
-            "SELECT id, message_id, message_type, channel_address, timestamp, body, dispatched, trace_parent FROM core.outbox WHERE message_id = $1"
+            "SELECT id, message_id, message_type, channel_address, timestamp, body, dispatched, trace_parent, partition_key, headers FROM core.outbox WHERE message_id = $1"
         )
             .bind(message_id)
             .fetch_one(pool)
@@ -133,6 +390,25 @@ mod tests {
             .ok()
     }
 
+    // Creates the schema and a dispatcher whose `memory://` channels can be inspected and failed
+    async fn setup_memory_harness(pool: &PgPool) -> Dispatcher {
+        let schema_sql = include_str!("../schema.sql");
+        pool.execute(schema_sql).await.expect("Failed to create schema");
+
+        let config = Config::load_test().expect("Failed to load config for test");
+        Dispatcher::new(setup_aws_clients(&config).await)
+    }
+
+    // Helper function to check whether a message has been dead-lettered
+    async fn is_dead_lettered(pool: &PgPool, message_id: &str) -> bool {
+        sqlx::query("SELECT dead_lettered IS NOT NULL AS dead_lettered FROM core.outbox WHERE message_id = $1")
+            .bind(message_id)
+            .fetch_one(pool)
+            .await
+            .expect("Failed to fetch message")
+            .get("dead_lettered")
+    }
+
     /// This is a full integration test.
     /// It requires:
     /// 1. A running Postgres database (configure with DATABASE_URL env var).
@@ -157,7 +433,7 @@ mod tests {
             // --- ARRANGE ---
 
             let config = Config::load_test().expect("Failed to load config for test");
-            let (sqs_client, sns_client) = setup_aws_clients(&config).await;
+            let dispatcher = Dispatcher::new(setup_aws_clients(&config).await);
             let channel_address = case;
 
             let message_id = insert_test_message(&pool, channel_address).await;
@@ -166,7 +442,7 @@ mod tests {
             assert!(msg.is_some(), "Test message was not inserted");
 
             // --- ACT ---
-            let result = sweep_outbox_and_send(&pool, &sqs_client, &sns_client, &10).await;
+            let result = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
 
             // --- ASSERT ---
             assert!(result.is_ok(), "Sweeper returned an error: {:?}", result.err());
@@ -190,14 +466,14 @@ mod tests {
 
         // 1. Get SQS config.
         let config = Config::load_test().expect("Failed to load config for test");
-        let (sqs_client, sns_client) = setup_aws_clients(&config).await;
+        let dispatcher = Dispatcher::new(setup_aws_clients(&config).await);
 
         // 2. Ensure no messages are in the DB
         let initial_messages = outbox::get_pending_messages(&pool, "test.topic", &10).await.unwrap();
         assert_eq!(initial_messages.len(), 0, "Database was not empty at start");
 
         // --- ACT ---
-        let result = sweep_outbox_and_send(&pool, &sqs_client, &sns_client, &10).await;
+        let result = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
 
         // --- ASSERT ---
 
@@ -219,7 +495,7 @@ mod tests {
 
         // 1. Get SQS config (we need a valid client, but a bad queue URL)
         let config = Config::load_test().expect("Failed to load config for test");
-        let (sqs_client, sns_client) = setup_aws_clients(&config).await;
+        let dispatcher = Dispatcher::new(setup_aws_clients(&config).await);
         let invalid_queue_url = "https_sqs_fake_url_that_does_not_exist";
 
         // 2. Insert a test message
@@ -233,7 +509,7 @@ mod tests {
 
         // --- ACT ---
         // Run the sweeper with the INVALID queue URL
-        let _result = sweep_outbox_and_send(&pool, &sqs_client, &sns_client, &10).await;
+        let _result = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
 
         // --- ASSERT ---
         let final_messages = outbox::get_pending_messages(&pool, "test.topic", &10).await.unwrap();
@@ -243,4 +519,434 @@ mod tests {
         assert_eq!(final_message.message_id, message_id, "Wrong message found after rollback");
             assert_eq!(final_message.dispatched, None, "Message not marked as dispatched");
     }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_memory_sweep_sends_and_marks_messages(pool: PgPool) {
+        // --- ARRANGE ---
+        let dispatcher = setup_memory_harness(&pool).await;
+        let first = insert_test_message(&pool, "memory://orders").await;
+        let second = insert_test_message(&pool, "memory://orders").await;
+
+        // --- ACT ---
+        let result = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(result.is_ok(), "Sweeper returned an error: {:?}", result.err());
+        assert_eq!(dispatcher.memory_transport().batches(), vec![("orders".to_string(), vec![first.clone(), second.clone()])]);
+
+        let remaining_messages = outbox::get_pending_messages(&pool, "test.topic", &10).await.unwrap();
+        assert_eq!(remaining_messages.len(), 0, "Messages were not marked as 'sent'");
+        assert_ne!(get_message(&pool, first).await.unwrap().dispatched, None);
+        assert_ne!(get_message(&pool, second).await.unwrap().dispatched, None);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_memory_sweep_leaves_messages_when_the_call_fails(pool: PgPool) {
+        // --- ARRANGE ---
+        let dispatcher = setup_memory_harness(&pool).await;
+        let message_id = insert_test_message(&pool, "memory://orders").await;
+        dispatcher.memory_transport().fail_next_calls(1);
+
+        // --- ACT ---
+        let failed_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+        let remaining_messages = outbox::get_pending_messages(&pool, "test.topic", &10).await.unwrap();
+        let retried_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(failed_sweep.is_ok(), "A failed dispatch should not fail the sweep: {:?}", failed_sweep.err());
+        assert_eq!(remaining_messages.len(), 1, "Message was marked as 'sent' after the call failed");
+
+        assert!(retried_sweep.is_ok(), "Sweeper returned an error: {:?}", retried_sweep.err());
+        assert_ne!(get_message(&pool, message_id).await.unwrap().dispatched, None, "Message was not sent on the next sweep");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_memory_sweep_retries_and_dead_letters_rejected_messages(pool: PgPool) {
+        // --- ARRANGE ---
+        let dispatcher = setup_memory_harness(&pool).await;
+        let accepted = insert_test_message(&pool, "memory://orders").await;
+        let retryable = insert_test_message(&pool, "memory://orders").await;
+        let permanent = insert_test_message(&pool, "memory://orders").await;
+        dispatcher.memory_transport().fail_message(&retryable, false);
+        dispatcher.memory_transport().fail_message(&permanent, true);
+
+        // --- ACT ---
+        let result = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(result.is_ok(), "Sweeper returned an error: {:?}", result.err());
+
+        assert_ne!(get_message(&pool, accepted.clone()).await.unwrap().dispatched, None, "Accepted message was not marked as 'sent'");
+        assert!(!is_dead_lettered(&pool, &accepted).await);
+
+        assert_eq!(get_message(&pool, retryable.clone()).await.unwrap().dispatched, None, "Rejected message was marked as 'sent'");
+        assert!(!is_dead_lettered(&pool, &retryable).await, "Retryable message was dead-lettered");
+
+        assert_eq!(get_message(&pool, permanent.clone()).await.unwrap().dispatched, None, "Rejected message was marked as 'sent'");
+        assert!(is_dead_lettered(&pool, &permanent).await, "Permanently failed message was not dead-lettered");
+
+        let remaining_messages = outbox::get_pending_messages(&pool, "test.topic", &10).await.unwrap();
+        let remaining_ids: Vec<String> = remaining_messages.into_iter().map(|m| m.message_id).collect();
+        assert_eq!(remaining_ids, vec![retryable], "Only the retryable message should be left to sweep");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_routed_messages_are_only_resent_to_failed_routes(pool: PgPool) {
+        // --- ARRANGE ---
+        let routes = vec!["memory://first".to_string(), "memory://second".to_string()];
+        let channel_config = ChannelConfig {
+            routes: [("test.topic".to_string(), routes)].into(),
+            ..Default::default()
+        };
+        let dispatcher = setup_memory_harness(&pool).await.with_channel_config(channel_config);
+        let message_id = insert_test_message(&pool, "").await;
+        dispatcher.memory_transport().fail_next_calls(1);
+
+        // --- ACT ---
+        let first_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+        let dispatched_after_failure = get_message(&pool, message_id.clone()).await.unwrap().dispatched;
+        let second_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(first_sweep.is_ok(), "Sweeper returned an error: {:?}", first_sweep.err());
+        assert!(second_sweep.is_ok(), "Sweeper returned an error: {:?}", second_sweep.err());
+        assert_eq!(dispatched_after_failure, None, "Message was marked as 'sent' before every route had it");
+
+        assert_eq!(dispatcher.memory_transport().batches(), vec![
+            ("second".to_string(), vec![message_id.clone()]),
+            ("first".to_string(), vec![message_id.clone()]),
+        ], "Each route should receive the message exactly once");
+
+        let deliveries = sqlx::query("SELECT channel_address FROM core.outbox_deliveries WHERE dispatched IS NOT NULL ORDER BY channel_address")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let delivered_to: Vec<String> = deliveries.iter().map(|row| row.get("channel_address")).collect();
+        assert_eq!(delivered_to, vec!["memory://first", "memory://second"]);
+        assert_ne!(get_message(&pool, message_id).await.unwrap().dispatched, None, "Message was not marked as 'sent' once every route had it");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_rules_pick_the_destination_of_placeholder_messages(pool: PgPool) {
+        // --- ARRANGE ---
+        let channel_config: ChannelConfig = toml::from_str(r#"
+            [[rules]]
+            message_type = "test.topic"
+            when = "$.region == 'eu'"
+            destination = "memory://eu"
+
+            [[rules]]
+            when = "$.region != null"
+            destination = "memory://rest"
+        "#).unwrap();
+        let dispatcher = setup_memory_harness(&pool).await.with_channel_config(channel_config);
+        let eu = insert_test_message_with_body(&pool, r#"{ "region": "eu" }"#).await;
+        let us = insert_test_message_with_body(&pool, r#"{ "region": "us" }"#).await;
+        let unroutable = insert_test_message_with_body(&pool, r#"{ "country": "NZ" }"#).await;
+
+        // --- ACT ---
+        let result = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(result.is_ok(), "Sweeper returned an error: {:?}", result.err());
+        assert_eq!(dispatcher.memory_transport().batches(), vec![
+            ("eu".to_string(), vec![eu.clone()]),
+            ("rest".to_string(), vec![us.clone()]),
+        ]);
+        assert_ne!(get_message(&pool, eu).await.unwrap().dispatched, None, "Routed message was not marked as 'sent'");
+        assert_ne!(get_message(&pool, us).await.unwrap().dispatched, None, "Routed message was not marked as 'sent'");
+        assert!(is_dead_lettered(&pool, &unroutable).await, "Message no rule matched was not dead-lettered");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_rate_limited_messages_stay_pending(pool: PgPool) {
+        // --- ARRANGE ---
+        let channel_config: ChannelConfig = toml::from_str(r#"
+            [channels."memory://limited"]
+            messages_per_second = 1
+        "#).unwrap();
+        let dispatcher = setup_memory_harness(&pool).await.with_channel_config(channel_config);
+        let first = insert_test_message(&pool, "memory://limited").await;
+        let second = insert_test_message(&pool, "memory://limited").await;
+
+        // --- ACT ---
+        let first_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+        let second_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(first_sweep.is_ok(), "Sweeper returned an error: {:?}", first_sweep.err());
+        assert!(second_sweep.is_ok(), "Sweeper returned an error: {:?}", second_sweep.err());
+        assert_eq!(dispatcher.memory_transport().batches(), vec![("limited".to_string(), vec![first.clone()])], "Only one message should fit in the first second");
+
+        assert_ne!(get_message(&pool, first).await.unwrap().dispatched, None, "Sent message was not marked as 'sent'");
+        assert_eq!(get_message(&pool, second.clone()).await.unwrap().dispatched, None, "Rate limited message was marked as 'sent'");
+        assert!(!is_dead_lettered(&pool, &second).await, "Rate limited message was dead-lettered");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_retries_are_rate_limited(pool: PgPool) {
+        // --- ARRANGE ---
+        let channel_config: ChannelConfig = toml::from_str(r#"
+            [channels."memory://limited"]
+            requests_per_second = 1
+            retry_budget = 3
+        "#).unwrap();
+        let dispatcher = setup_memory_harness(&pool).await.with_channel_config(channel_config);
+        let message_id = insert_test_message(&pool, "memory://limited").await;
+        dispatcher.memory_transport().fail_next_calls(1);
+
+        // --- ACT ---
+        let result = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(result.is_ok(), "Sweeper returned an error: {:?}", result.err());
+        assert!(dispatcher.memory_transport().batches().is_empty(), "The retry should have waited for another request token, rather than succeeding");
+        assert_eq!(get_message(&pool, message_id.clone()).await.unwrap().dispatched, None, "Rate limited message was marked as 'sent'");
+        assert!(!is_dead_lettered(&pool, &message_id).await, "Rate limited message was dead-lettered");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_rate_limited_trial_call_is_made_later(pool: PgPool) {
+        // --- ARRANGE ---
+        let channel_config: ChannelConfig = toml::from_str(r#"
+            [channels."memory://recovering"]
+            requests_per_second = 1
+            circuit_failure_threshold = 1
+            circuit_cooldown_ms = 0
+        "#).unwrap();
+        let dispatcher = setup_memory_harness(&pool).await.with_channel_config(channel_config);
+        let message_id = insert_test_message(&pool, "memory://recovering").await;
+        dispatcher.memory_transport().fail_next_calls(1);
+
+        // --- ACT ---
+        let failed_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+        let rate_limited_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        let trial_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(failed_sweep.is_ok(), "Sweeper returned an error: {:?}", failed_sweep.err());
+        assert!(rate_limited_sweep.is_ok(), "Sweeper returned an error: {:?}", rate_limited_sweep.err());
+        assert!(trial_sweep.is_ok(), "Sweeper returned an error: {:?}", trial_sweep.err());
+        assert_eq!(dispatcher.memory_transport().batches(), vec![("recovering".to_string(), vec![message_id.clone()])], "The trial call should be made once the rate limit allows it");
+        assert_ne!(get_message(&pool, message_id).await.unwrap().dispatched, None, "Message was not sent by the trial call");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_open_circuit_skips_the_channel(pool: PgPool) {
+        // --- ARRANGE ---
+        let channel_config: ChannelConfig = toml::from_str(r#"
+            [channels."memory://flaky"]
+            circuit_failure_threshold = 1
+            circuit_cooldown_ms = 60000
+        "#).unwrap();
+        let dispatcher = setup_memory_harness(&pool).await.with_channel_config(channel_config);
+        let message_id = insert_test_message(&pool, "memory://flaky").await;
+        dispatcher.memory_transport().fail_next_calls(1);
+
+        // --- ACT ---
+        let first_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+        let second_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(first_sweep.is_ok(), "Sweeper returned an error: {:?}", first_sweep.err());
+        assert!(second_sweep.is_ok(), "Sweeper returned an error: {:?}", second_sweep.err());
+        assert!(dispatcher.memory_transport().batches().is_empty(), "The open circuit should have skipped the second sweep");
+        assert_eq!(get_message(&pool, message_id.clone()).await.unwrap().dispatched, None, "Skipped message was marked as 'sent'");
+        assert!(!is_dead_lettered(&pool, &message_id).await, "Skipped message was dead-lettered");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_rejecting_every_message_opens_the_circuit(pool: PgPool) {
+        // --- ARRANGE ---
+        let channel_config: ChannelConfig = toml::from_str(r#"
+            [channels."memory://down"]
+            circuit_failure_threshold = 1
+            circuit_cooldown_ms = 60000
+        "#).unwrap();
+        let dispatcher = setup_memory_harness(&pool).await.with_channel_config(channel_config);
+        let message_id = insert_test_message(&pool, "memory://down").await;
+        dispatcher.memory_transport().fail_message(&message_id, false);
+
+        // --- ACT ---
+        let first_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+        let second_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(first_sweep.is_ok(), "Sweeper returned an error: {:?}", first_sweep.err());
+        assert!(second_sweep.is_ok(), "Sweeper returned an error: {:?}", second_sweep.err());
+        assert_eq!(dispatcher.memory_transport().batches(), vec![("down".to_string(), vec![])], "The open circuit should have skipped the second sweep");
+        assert!(!dispatcher.circuit_allows("memory://down"), "The circuit should be open");
+        assert_eq!(get_message(&pool, message_id.clone()).await.unwrap().dispatched, None, "Rejected message was marked as 'sent'");
+        assert!(!is_dead_lettered(&pool, &message_id).await, "Rejected message was dead-lettered");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_messages_fail_over_to_the_secondary_destination(pool: PgPool) {
+        // --- ARRANGE ---
+        let channel_config: ChannelConfig = toml::from_str(r#"
+            [channels."memory://primary"]
+            failover_address = "memory://secondary"
+            circuit_failure_threshold = 1
+            circuit_cooldown_ms = 60000
+        "#).unwrap();
+        let dispatcher = setup_memory_harness(&pool).await.with_channel_config(channel_config);
+        dispatcher.memory_transport().fail_next_calls(1);
+
+        // --- ACT ---
+        let first = insert_test_message(&pool, "memory://primary").await;
+        let first_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+        let second = insert_test_message(&pool, "memory://primary").await;
+        let second_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(first_sweep.is_ok(), "Sweeper returned an error: {:?}", first_sweep.err());
+        assert!(second_sweep.is_ok(), "Sweeper returned an error: {:?}", second_sweep.err());
+        assert_eq!(dispatcher.memory_transport().batches(), vec![
+            ("secondary".to_string(), vec![first.clone()]),
+            ("secondary".to_string(), vec![second.clone()]),
+        ], "The failed call and then the open circuit should both fail over");
+        assert_ne!(get_message(&pool, first).await.unwrap().dispatched, None, "Failed over message was not marked as 'sent'");
+        assert_ne!(get_message(&pool, second).await.unwrap().dispatched, None, "Failed over message was not marked as 'sent'");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_shadow_copies_never_fail_the_primary(pool: PgPool) {
+        // --- ARRANGE ---
+        let channel_config: ChannelConfig = toml::from_str(r#"
+            [channels."memory://primary"]
+            shadow_address = "memory://shadow"
+
+            [channels."memory://unshadowed"]
+            shadow_address = "kafka://not-configured"
+        "#).unwrap();
+        let dispatcher = setup_memory_harness(&pool).await.with_channel_config(channel_config);
+        let shadowed = insert_test_message(&pool, "memory://primary").await;
+        let unshadowed = sqlx::query_scalar::<_, String>(
+            "INSERT INTO core.outbox (message_id, message_type, channel_address, body) VALUES ('unshadowed', 'other.topic', 'memory://unshadowed', '{}') RETURNING message_id"
+        ).fetch_one(&pool).await.unwrap();
+
+        // --- ACT ---
+        let result = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+        for _ in 0..50 {
+            if dispatcher.memory_transport().batches().len() == 3 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        // --- ASSERT ---
+        assert!(result.is_ok(), "Sweeper returned an error: {:?}", result.err());
+        let mut batches = dispatcher.memory_transport().batches();
+        batches.sort();
+        assert_eq!(batches, vec![
+            ("primary".to_string(), vec![shadowed.clone()]),
+            ("shadow".to_string(), vec![shadowed.clone()]),
+            ("unshadowed".to_string(), vec![unshadowed.clone()]),
+        ]);
+        assert_ne!(get_message(&pool, shadowed).await.unwrap().dispatched, None, "Shadowed message was not marked as 'sent'");
+        assert_ne!(get_message(&pool, unshadowed).await.unwrap().dispatched, None, "A failed shadow copy should not stop the message being marked as 'sent'");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_shadow_copies_respect_the_shadow_rate_limit(pool: PgPool) {
+        // --- ARRANGE ---
+        let channel_config: ChannelConfig = toml::from_str(r#"
+            [channels."memory://primary"]
+            shadow_address = "memory://shadow"
+
+            [channels."memory://shadow"]
+            requests_per_second = 1
+        "#).unwrap();
+        let dispatcher = setup_memory_harness(&pool).await.with_channel_config(channel_config);
+
+        // --- ACT ---
+        let first = insert_test_message(&pool, "memory://primary").await;
+        let first_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+        let second = insert_test_message(&pool, "memory://primary").await;
+        let second_sweep = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+        for _ in 0..50 {
+            if dispatcher.memory_transport().batches().len() == 3 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        // --- ASSERT ---
+        assert!(first_sweep.is_ok(), "Sweeper returned an error: {:?}", first_sweep.err());
+        assert!(second_sweep.is_ok(), "Sweeper returned an error: {:?}", second_sweep.err());
+        let shadow_batches: Vec<Vec<String>> = dispatcher.memory_transport().batches().into_iter()
+            .filter(|(channel, _)| channel == "shadow")
+            .map(|(_, accepted)| accepted)
+            .collect();
+        assert_eq!(shadow_batches, vec![vec![first.clone()]], "Only the first copy should fit within the shadow's rate limit");
+        assert_ne!(get_message(&pool, second).await.unwrap().dispatched, None, "A rate limited shadow should not hold up the primary");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_transform_script_errors_dead_letter_the_message(pool: PgPool) {
+        // --- ARRANGE ---
+        let mut transforms = Transforms::default();
+        transforms.add("test.topic", r#"if type_of(body) == "string" { throw "the body is not JSON"; }"#).unwrap();
+        let dispatcher = setup_memory_harness(&pool).await.with_transforms(transforms);
+        let message_id = insert_test_message(&pool, "memory://transformed").await;
+
+        // --- ACT ---
+        let result = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(result.is_ok(), "Sweeper returned an error: {:?}", result.err());
+        assert!(dispatcher.memory_transport().batches().is_empty(), "The message should not have reached the transport");
+        assert!(is_dead_lettered(&pool, &message_id).await, "Message whose script failed was not dead-lettered");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_bodies_that_do_not_match_their_schema_are_dead_lettered(pool: PgPool) {
+        // --- ARRANGE ---
+        let mut schemas = Schemas::default();
+        schemas.add("test.topic", &serde_json::json!({ "type": "object", "required": ["region"] })).unwrap();
+        let dispatcher = setup_memory_harness(&pool).await.with_schemas(schemas);
+        let valid = insert_test_message_with_body(&pool, r#"{ "region": "eu" }"#).await;
+        let missing_region = insert_test_message_with_body(&pool, r#"{ "country": "NZ" }"#).await;
+        let not_json = insert_test_message(&pool, "memory://validated").await;
+        sqlx::query("UPDATE core.outbox SET channel_address = 'memory://validated'").execute(&pool).await.unwrap();
+
+        // --- ACT ---
+        let result = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(result.is_ok(), "Sweeper returned an error: {:?}", result.err());
+        assert_eq!(dispatcher.memory_transport().batches(), vec![("validated".to_string(), vec![valid.clone()])]);
+        assert_ne!(get_message(&pool, valid).await.unwrap().dispatched, None, "Valid message was not marked as 'sent'");
+        assert!(is_dead_lettered(&pool, &missing_region).await, "Message missing a required field was not dead-lettered");
+        assert!(is_dead_lettered(&pool, &not_json).await, "Message whose body is not JSON was not dead-lettered");
+
+        let reason: String = sqlx::query_scalar("SELECT dead_letter_reason FROM core.outbox WHERE message_id = $1")
+            .bind(&missing_region)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(reason.contains("\"region\" is a required property"), "{reason}");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_bodies_are_validated_after_their_transform(pool: PgPool) {
+        // --- ARRANGE ---
+        let mut transforms = Transforms::default();
+        transforms.add("test.topic", "body.region = body.country; body.remove(\"country\");").unwrap();
+        let mut schemas = Schemas::default();
+        schemas.add("test.topic", &serde_json::json!({ "type": "object", "required": ["region"], "not": { "required": ["country"] } })).unwrap();
+        let dispatcher = setup_memory_harness(&pool).await.with_transforms(transforms).with_schemas(schemas);
+        let message_id = insert_test_message_with_body(&pool, r#"{ "country": "NZ" }"#).await;
+        sqlx::query("UPDATE core.outbox SET channel_address = 'memory://validated'").execute(&pool).await.unwrap();
+
+        // --- ACT ---
+        let result = sweep_outbox_and_send(&pool, &dispatcher, &10).await;
+
+        // --- ASSERT ---
+        assert!(result.is_ok(), "Sweeper returned an error: {:?}", result.err());
+        assert_eq!(dispatcher.memory_transport().batches(), vec![("validated".to_string(), vec![message_id.clone()])], "The transformed body should have matched the schema");
+        assert_ne!(get_message(&pool, message_id.clone()).await.unwrap().dispatched, None, "Transformed message was not marked as 'sent'");
+        assert!(!is_dead_lettered(&pool, &message_id).await, "Transformed message was dead-lettered");
+    }
 }